@@ -0,0 +1,73 @@
+//! Instructions per second on a loop-heavy program, run with `cargo bench`
+//!
+//! Compares running a program decoded when it is loaded against decoding every instruction as
+//! it runs, which is what a VM does with code put into `VM::program` directly.
+//!
+//! The decoding side goes through `Instruction::decode`, the fallback added alongside the
+//! predecoded dispatch, not the byte by byte operand reads the VM dispatched with before. The
+//! speedup is between the two paths of the current VM, not against that older dispatch loop.
+
+extern crate rust_vm;
+
+use std::time::{Duration, Instant};
+
+use rust_vm::{Assembler, Bytecode, VM};
+
+const ITERATIONS: u32 = 60_000;
+const RUNS: u32 = 50;
+
+/// Counts $0 up to ITERATIONS, every pass adding, shifting, comparing and branching
+fn program() -> Bytecode {
+  let source = format!(
+    "load $0 #0\n\
+     load $1 #1\n\
+     load $2 #{}\n\
+     load $3 @loop\n\
+     loop: add $0 $1 $0\n\
+     xor $0 $1 $4\n\
+     shl $4 $1 $5\n\
+     cmp $0 $2\n\
+     jlt $3\n\
+     hlt\n",
+    ITERATIONS
+  );
+  Assembler::new().assemble_bytecode(&source).unwrap()
+}
+
+/// Runs `setup`'s VM to the end RUNS times, returning the time taken and instructions executed
+fn measure<F: Fn() -> VM>(setup: F) -> (Duration, u64) {
+  let mut elapsed = Duration::default();
+  for _ in 0..RUNS {
+    let mut vm = setup();
+    let start = Instant::now();
+    vm.run().unwrap();
+    elapsed += start.elapsed();
+    assert_eq!(ITERATIONS as i32, vm.registers[0]);
+  }
+  // 4 loads and the hlt, then 5 instructions a pass
+  let instructions = u64::from(RUNS) * (5 + 5 * u64::from(ITERATIONS));
+  (elapsed, instructions)
+}
+
+fn report(name: &str, (elapsed, instructions): (Duration, u64)) -> f64 {
+  let per_second = instructions as f64 / elapsed.as_secs_f64();
+  println!("{:<12} {:>12} instructions in {:>10.3?}, {:>14.0} per second", name, instructions, elapsed, per_second);
+  per_second
+}
+
+fn main() {
+  let bytecode = program();
+  let bytes = bytecode.to_bytes();
+
+  let decoding = report("decoding", measure(|| {
+    let mut vm = VM::new();
+    vm.program = bytecode.code.clone();
+    vm
+  }));
+  let predecoded = report("predecoded", measure(|| {
+    let mut vm = VM::new();
+    vm.load_program(&bytes).unwrap();
+    vm
+  }));
+  println!("speedup      {:.2}x", predecoded / decoding);
+}
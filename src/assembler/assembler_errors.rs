@@ -0,0 +1,88 @@
+use std::error::Error;
+use std::fmt;
+
+use assembler::{Section, Token};
+use instruction::OperandKind;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum AssemblerError {
+  /// The opcode field does not hold a known opcode
+  NonOpcodeInOpcodeField { token: Option<Token> },
+  /// The mnemonic does not name any opcode
+  UnknownMnemonic { name: String },
+  /// The directive is not supported by the assembler
+  UnknownDirective { name: String },
+  /// The instruction has the wrong number of operands for its opcode
+  WrongOperandCount { mnemonic: &'static str, min: usize, max: usize, got: usize },
+  /// An operand is not of the kind the opcode expects at its position, which starts at 1
+  WrongOperandKind { mnemonic: &'static str, position: usize, expected: OperandKind, found: Token },
+  /// A register operand names a register the VM does not have
+  RegisterOutOfRange { register: u8 },
+  /// An operand cannot be encoded in an instruction
+  UnexpectedOperand { token: Token },
+  /// An integer literal does not fit in the field it is encoded into
+  IntegerOutOfRange { value: i64, min: i64, max: i64 },
+  /// An operand refers to a label that is never declared
+  UndefinedLabel { name: String },
+  /// A label is declared more than once
+  DuplicateLabel { name: String },
+  /// A directive was given operands it does not take, `expected` describes the right ones
+  WrongDirectiveOperands { name: String, expected: &'static str },
+  /// An instruction or data directive appears in the section it cannot be assembled into
+  WrongSection { token: Token, section: Section },
+  /// The source could not be parsed, `rest` is the input left unparsed
+  ParseError { rest: String },
+}
+
+impl fmt::Display for AssemblerError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      AssemblerError::NonOpcodeInOpcodeField { token } => {
+        write!(f, "expected an opcode, found {:?}", token)
+      }
+      AssemblerError::UnknownMnemonic { name } => write!(f, "unknown mnemonic `{}`", name),
+      AssemblerError::UnknownDirective { name } => write!(f, "unknown directive `.{}`", name),
+      AssemblerError::WrongOperandCount { mnemonic, min, max, got } if min != max => {
+        write!(f, "`{}` expects {} or {} operands, got {}", mnemonic, min, max, got)
+      }
+      AssemblerError::WrongOperandCount { mnemonic, max, got, .. } => {
+        let plural = if *max == 1 { "" } else { "s" };
+        write!(f, "`{}` expects {} operand{}, got {}", mnemonic, max, plural, got)
+      }
+      AssemblerError::WrongOperandKind { mnemonic, position, expected, found } => {
+        let expected = match expected {
+          OperandKind::Register => "a register",
+          OperandKind::Integer16 => "an integer or a label",
+        };
+        write!(f, "`{}` operand {} must be {}, found `{}`", mnemonic, position, expected, found)
+      }
+      AssemblerError::RegisterOutOfRange { register } => {
+        write!(f, "register `${}` does not exist", register)
+      }
+      AssemblerError::UnexpectedOperand { token } => {
+        write!(f, "operand `{}` cannot be encoded", token)
+      }
+      AssemblerError::IntegerOutOfRange { value, min, max } => {
+        write!(f, "integer {} is out of range, expected {} to {}", value, min, max)
+      }
+      AssemblerError::UndefinedLabel { name } => write!(f, "undefined label `{}`", name),
+      AssemblerError::DuplicateLabel { name } => {
+        write!(f, "label `{}` is declared more than once", name)
+      }
+      AssemblerError::WrongDirectiveOperands { name, expected } => {
+        write!(f, "`.{}` expects {}", name, expected)
+      }
+      AssemblerError::WrongSection { token, section } => {
+        write!(f, "`{}` cannot be used in the {} section", token, section)
+      }
+      AssemblerError::ParseError { rest } => {
+        match rest.lines().next() {
+          Some(line) if !line.trim().is_empty() => write!(f, "unexpected `{}`", line.trim()),
+          _ => write!(f, "unexpected end of line")
+        }
+      }
+    }
+  }
+}
+
+impl Error for AssemblerError {}
@@ -0,0 +1,81 @@
+use std::fmt;
+
+use assembler::assembler_errors::AssemblerError;
+
+/// Location of a problem in the assembly source, lines and columns start at 1
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Span {
+  pub line: usize,
+  pub column: usize,
+  /// Number of characters the problem covers, at least 1
+  pub len: usize,
+}
+
+/// An assembler error tied to the place in the source that caused it
+#[derive(Debug, PartialEq, Clone)]
+pub struct Diagnostic {
+  pub error: AssemblerError,
+  pub span: Span,
+  pub message: String,
+  /// The full source line the span points into
+  pub source_line: String,
+}
+
+impl Diagnostic {
+  pub fn new(error: AssemblerError, span: Span, source_line: &str) -> Diagnostic {
+    Diagnostic {
+      message: error.to_string(),
+      error,
+      span,
+      source_line: source_line.to_string(),
+    }
+  }
+
+  /// The source line with carets under the offending characters
+  ///
+  /// ```text
+  ///   |
+  /// 3 | lod $0 #1
+  ///   | ^^^
+  /// ```
+  pub fn excerpt(&self) -> String {
+    let number = self.span.line.to_string();
+    let gutter = " ".repeat(number.len());
+    // keep tabs so the carets line up with what the terminal shows
+    let padding: String = self.source_line
+      .chars()
+      .take(self.span.column - 1)
+      .map(|c| if c == '\t' { '\t' } else { ' ' })
+      .collect();
+    format!(
+      "{} |\n{} | {}\n{} | {}{}",
+      gutter, number, self.source_line, gutter, padding, "^".repeat(self.span.len)
+    )
+  }
+}
+
+impl fmt::Display for Diagnostic {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(
+      f,
+      "error: {}\n --> line {}, column {}\n{}",
+      self.message, self.span.line, self.span.column, self.excerpt()
+    )
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_excerpt() {
+    let diagnostic = Diagnostic::new(
+      AssemblerError::UnknownMnemonic { name: "lod".to_string() },
+      Span { line: 12, column: 8, len: 3 },
+      "start:\tlod $0 #1"
+    );
+    assert_eq!("unknown mnemonic `lod`", diagnostic.message);
+    assert_eq!("   |\n12 | start:\tlod $0 #1\n   |       \t^^^", diagnostic.excerpt());
+  }
+}
@@ -1,6 +1,7 @@
 use assembler::Token;
 use assembler::instruction_parsers::AssemblerInstruction;
-use assembler::operand_parsers::operand;
+use assembler::label_parsers::label_declaration;
+use assembler::operand_parsers::{char_literal, operand, signed_literal};
 use nom::types::CompleteStr;
 use nom::alpha1;
 
@@ -14,18 +15,31 @@ named!(directive_declaration<CompleteStr, Token>,
   )
 );
 
+// data directives take plain integers, `.integer 42` as well as `.integer #42`
+named!(bare_int_operand<CompleteStr, Token>,
+  ws!(
+    map!(alt!(char_literal | signed_literal), |value| Token::IntegerOperand{ value })
+  )
+);
+
+named!(directive_operand<CompleteStr, Token>,
+  alt!(
+    operand | bare_int_operand
+  )
+);
+
 named!(directive_combined<CompleteStr, AssemblerInstruction>,
   do_parse!(
-    tag!(".") >>
+    l: opt!(label_declaration) >>
     name: directive_declaration >>
-    o1: opt!(operand) >>
-    o2: opt!(operand) >>
-    o3: opt!(operand) >>
+    o1: opt!(directive_operand) >>
+    o2: opt!(directive_operand) >>
+    o3: opt!(directive_operand) >>
     (
       AssemblerInstruction {
         opcode: None,
         directive: Some(name),
-        label: None,
+        label: l,
         operand1: o1,
         operand2: o2,
         operand3: o3
@@ -40,3 +54,29 @@ named!(pub directive<CompleteStr, AssemblerInstruction>,
     ( ins )
   )
 );
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_parse_directive() {
+    let (rest, instr) = directive(CompleteStr(".data")).unwrap();
+    assert_eq!(CompleteStr(""), rest);
+    assert_eq!(Some(Token::Directive { name: "data".to_string() }), instr.directive);
+    assert_eq!(None, instr.operand1);
+  }
+
+  #[test]
+  fn test_parse_directive_operands() {
+    let (rest, instr) = directive(CompleteStr("greeting: .asciiz \"hi there\"")).unwrap();
+    assert_eq!(CompleteStr(""), rest);
+    assert_eq!(Some("greeting".to_string()), instr.label_name());
+    assert_eq!(Some(Token::StringOperand { value: "hi there".to_string() }), instr.operand1);
+
+    for source in [".integer 42", ".integer #42", ".integer 0x2A", ".integer '*'"].iter() {
+      let (_, instr) = directive(CompleteStr(source)).unwrap();
+      assert_eq!(Some(Token::IntegerOperand { value: 42 }), instr.operand1, "{}", source);
+    }
+  }
+}
@@ -2,10 +2,12 @@ use assembler::opcode_parsers::*;
 use assembler::operand_parsers::operand;
 use assembler::directive_parsers::directive;
 use assembler::label_parsers::label_declaration;
-use assembler::Token;
+use assembler::{Section, Token};
+use assembler::assembler_errors::AssemblerError;
+use assembler::symbols::SymbolTable;
+use instruction::{OpCode, OpCodeInfo, OperandKind, REGISTER_COUNT};
 
 use nom::types::CompleteStr;
-use std;
 
 #[derive(Debug, PartialEq)]
 pub struct AssemblerInstruction {
@@ -18,50 +20,237 @@ pub struct AssemblerInstruction {
 }
 
 impl AssemblerInstruction {
-  pub fn to_bytes(&self) -> Vec<u8> {
-    let mut result = vec![];
-    match self.opcode {
-      Some(Token::Op{code}) => result.push(code as u8),
+  pub fn to_bytes(&self, symbols: &SymbolTable) -> Result<Vec<u8>, AssemblerError> {
+    let info = match (self.encoded_opcode(), &self.directive) {
+      (Some(code), _) => code.info().ok_or_else(|| {
+        AssemblerError::NonOpcodeInOpcodeField { token: self.opcode.clone() }
+      })?,
+      (None, Some(Token::Directive { name })) => return self.directive_to_bytes(name, symbols),
+      // a label on a line of its own
+      (None, None) => return Ok(vec![]),
       _ => {
-        println!("Unknown OpCode found {:?}", self.opcode);
-        std::process::exit(1);
+        return Err(AssemblerError::NonOpcodeInOpcodeField { token: self.opcode.clone() });
+      }
+    };
+    self.check_operands(info)?;
+    if let Some(value) = self.wide_load_value() {
+      return self.wide_load_to_bytes(value);
+    }
+
+    let mut result = vec![info.opcode as u8];
+    for operand in self.operands() {
+      AssemblerInstruction::extract_operand(operand, symbols, &mut result)?;
+    }
+    // pad to the encoded size of the opcode
+    result.resize(info.size, 0);
+
+    Ok(result)
+  }
+
+  /// Bytes a directive adds to its section, section switches add none
+  fn directive_to_bytes(&self, name: &str, symbols: &SymbolTable) -> Result<Vec<u8>, AssemblerError> {
+    let wrong_operands = |expected| AssemblerError::WrongDirectiveOperands { name: name.to_string(), expected };
+    match (name, self.operands().as_slice()) {
+      ("code", []) | ("data", []) => Ok(vec![]),
+      ("code", _) | ("data", _) => Err(wrong_operands("no operands")),
+      // NUL terminated
+      ("asciiz", [Token::StringOperand { value }]) => {
+        let mut bytes = value.clone().into_bytes();
+        bytes.push(0);
+        Ok(bytes)
       }
+      ("asciiz", _) => Err(wrong_operands("a string")),
+      ("integer", [Token::IntegerOperand { value }]) => {
+        check_range(*value, i64::from(i32::MIN), i64::from(u32::MAX))?;
+        Ok((*value as u32).to_be_bytes().to_vec())
+      }
+      ("integer", [Token::LabelUsage { name: label }]) => match symbols.symbol_value(label) {
+        Some(offset) => Ok(offset.to_be_bytes().to_vec()),
+        None => Err(AssemblerError::UndefinedLabel { name: label.clone() })
+      },
+      ("integer", _) => Err(wrong_operands("an integer or a label")),
+      ("byte", [Token::IntegerOperand { value }]) => {
+        check_range(*value, i64::from(i8::MIN), i64::from(u8::MAX))?;
+        Ok(vec![*value as u8])
+      }
+      ("byte", _) => Err(wrong_operands("an integer")),
+      ("space", [Token::IntegerOperand { value }]) => {
+        check_range(*value, 0, i64::from(u16::MAX))?;
+        Ok(vec![0; *value as usize])
+      }
+      ("space", _) => Err(wrong_operands("a byte count")),
+      _ => Err(AssemblerError::UnknownDirective { name: name.to_string() })
+    }
+  }
+
+  /// The section a `.code` or `.data` directive switches to
+  pub fn section(&self) -> Option<Section> {
+    match self.directive_name() {
+      Some("code") => Some(Section::Code),
+      Some("data") => Some(Section::Data),
+      _ => None
+    }
+  }
+
+  /// Instructions only go in the code section and data directives only in the data section
+  pub fn check_section(&self, section: Section) -> Result<(), AssemblerError> {
+    let token = match (section, &self.opcode, self.directive_name()) {
+      (Section::Data, Some(opcode), _) => opcode,
+      (Section::Code, None, Some("asciiz")) |
+      (Section::Code, None, Some("integer")) |
+      (Section::Code, None, Some("byte")) |
+      (Section::Code, None, Some("space")) => self.directive.as_ref().unwrap(),
+      _ => return Ok(())
+    };
+    Err(AssemblerError::WrongSection { token: token.clone(), section })
+  }
+
+  fn directive_name(&self) -> Option<&str> {
+    match &self.directive {
+      Some(Token::Directive { name }) => Some(name),
+      _ => None
     }
+  }
 
-    for operand in &[&self.operand1, &self.operand2, &self.operand3] {
-      match operand {
-        Some(t) => AssemblerInstruction::extract_operand(t, &mut result),
-        None => {}
+  /// Checks the operands against the ones the opcode table lists for the opcode, `hlt` is
+  /// checked as written and takes the status register of EXIT or nothing
+  fn check_operands(&self, info: &OpCodeInfo) -> Result<(), AssemblerError> {
+    let operands = self.operands();
+    let (mnemonic, min, kinds) = match self.opcode {
+      Some(Token::Op { code: OpCode::HLT }) => {
+        (OpCode::HLT.mnemonic(), 0, OpCode::EXIT.info().map_or(&[][..], |exit| exit.operands))
+      }
+      _ => (info.mnemonic, info.operands.len(), info.operands)
+    };
+    if operands.len() < min || operands.len() > kinds.len() {
+      return Err(AssemblerError::WrongOperandCount { mnemonic, min, max: kinds.len(), got: operands.len() });
+    }
+    for (index, (operand, kind)) in operands.iter().zip(kinds.iter()).enumerate() {
+      match (kind, operand) {
+        (OperandKind::Register, Token::Register { reg_num }) => {
+          if *reg_num as usize >= REGISTER_COUNT {
+            return Err(AssemblerError::RegisterOutOfRange { register: *reg_num });
+          }
+        }
+        (OperandKind::Integer16, Token::IntegerOperand { .. }) |
+        (OperandKind::Integer16, Token::LabelUsage { .. }) => {}
+        _ => {
+          return Err(AssemblerError::WrongOperandKind {
+            mnemonic,
+            position: index + 1,
+            expected: *kind,
+            found: (*operand).clone()
+          });
+        }
       }
     }
-    // pad to full 32 bit length
-    while result.len() < 4 {
-      result.push(0);
+    Ok(())
+  }
+
+  /// The opcode the instruction is encoded with, `hlt $r` is the EXIT opcode
+  fn encoded_opcode(&self) -> Option<OpCode> {
+    match (&self.opcode, self.operands().len()) {
+      (Some(Token::Op { code: OpCode::HLT }), 1) => Some(OpCode::EXIT),
+      (Some(Token::Op { code }), _) => Some(*code),
+      _ => None
+    }
+  }
+
+  /// LOAD zero-extends its 16 bit operand, so any other constant needs the wide form
+  fn wide_load_value(&self) -> Option<i64> {
+    match (&self.opcode, &self.operand2) {
+      (Some(Token::Op { code: OpCode::LOAD }), Some(Token::IntegerOperand { value }))
+        if *value < 0 || *value > i64::from(u16::MAX) => Some(*value),
+      _ => None
     }
+  }
 
-    result
+  /// Assembles `load $r #value` as LOAD of the low half followed by LUI of the high half
+  fn wide_load_to_bytes(&self, value: i64) -> Result<Vec<u8>, AssemblerError> {
+    check_range(value, i64::from(i32::MIN), i64::from(u32::MAX))?;
+    let bits = value as u32;
+    let half = |code: OpCode, value: u32| AssemblerInstruction {
+      opcode: Some(Token::Op { code }),
+      label: None,
+      directive: None,
+      operand1: self.operand1.clone(),
+      operand2: Some(Token::IntegerOperand { value: i64::from(value) }),
+      operand3: None
+    };
+    let symbols = SymbolTable::new();
+    let mut result = half(OpCode::LOAD, bits & 0xFFFF).to_bytes(&symbols)?;
+    result.append(&mut half(OpCode::LUI, bits >> 16).to_bytes(&symbols)?);
+    Ok(result)
   }
 
-  fn extract_operand(token: &Token, results: &mut Vec<u8>) {
+  /// Number of bytes `to_bytes` produces, without needing label addresses
+  pub fn encoded_len(&self) -> usize {
+    match self.opcode {
+      Some(Token::Op { .. }) if self.wide_load_value().is_some() => 8,
+      Some(Token::Op { .. }) => self.encoded_opcode().and_then(OpCode::info).map_or(0, |info| info.size),
+      // label addresses are not known yet but always take 4 bytes
+      None if self.directive_name() == Some("integer") => 4,
+      None => self.to_bytes(&SymbolTable::new()).map_or(0, |bytes| bytes.len()),
+      _ => 0
+    }
+  }
+
+  /// The operands that are present, in order
+  pub fn operands(&self) -> Vec<&Token> {
+    [&self.operand1, &self.operand2, &self.operand3].iter().cloned().flatten().collect()
+  }
+
+  pub fn is_label(&self) -> bool {
+    self.label.is_some()
+  }
+
+  pub fn label_name(&self) -> Option<String> {
+    match &self.label {
+      Some(Token::LabelDeclaration { name }) => Some(name.clone()),
+      _ => None
+    }
+  }
+
+  fn extract_operand(token: &Token, symbols: &SymbolTable, results: &mut Vec<u8>) -> Result<(), AssemblerError> {
     match token {
       Token::Register { reg_num } => {
         results.push(*reg_num);
       }
       Token::IntegerOperand { value } => {
-        let value_u16 = *value as u16;
-        let last_4_bytes = value_u16 as u8;
-        let first_4_bytes = (value_u16 >> 8) as u8;
-        results.push(first_4_bytes);
-        results.push(last_4_bytes);
+        AssemblerInstruction::extract_16_bits(*value, results)?;
+      }
+      Token::LabelUsage { name } => {
+        match symbols.symbol_value(name) {
+          Some(offset) => AssemblerInstruction::extract_16_bits(i64::from(offset), results)?,
+          None => return Err(AssemblerError::UndefinedLabel { name: name.clone() })
+        }
       }
       _ => {
-        println!("Unknown operand found {:?}", token);
-        std::process::exit(1);
+        return Err(AssemblerError::UnexpectedOperand { token: token.clone() });
       }
     }
+    Ok(())
+  }
+
+  fn extract_16_bits(value: i64, results: &mut Vec<u8>) -> Result<(), AssemblerError> {
+    // 16 bit fields take unsigned values or negative ones in two's complement
+    check_range(value, i64::from(i16::MIN), i64::from(u16::MAX))?;
+    let value_u16 = value as u16;
+    let last_4_bytes = value_u16 as u8;
+    let first_4_bytes = (value_u16 >> 8) as u8;
+    results.push(first_4_bytes);
+    results.push(last_4_bytes);
+    Ok(())
   }
 }
 
+fn check_range(value: i64, min: i64, max: i64) -> Result<(), AssemblerError> {
+  if value < min || value > max {
+    return Err(AssemblerError::IntegerOutOfRange { value, min, max });
+  }
+  Ok(())
+}
+
 named!(instruction_combined<CompleteStr, AssemblerInstruction>,
   do_parse!(
     l: opt!(label_declaration) >>
@@ -82,9 +271,25 @@ named!(instruction_combined<CompleteStr, AssemblerInstruction>,
   )
 );
 
+named!(label_only<CompleteStr, AssemblerInstruction>,
+  do_parse!(
+    l: label_declaration >>
+    (
+      AssemblerInstruction{
+        opcode: None,
+        label: Some(l),
+        directive: None,
+        operand1: None,
+        operand2: None,
+        operand3: None
+      }
+    )
+  )
+);
+
 named!(pub instruction<CompleteStr, AssemblerInstruction>,
   do_parse!(
-    ins: alt!(instruction_combined | directive) >>
+    ins: alt!(instruction_combined | directive | label_only) >>
     (
       ins
     )
@@ -94,7 +299,7 @@ named!(pub instruction<CompleteStr, AssemblerInstruction>,
 #[cfg(test)]
 mod tests {
   use super::*;
-  use instruction::OpCode;
+  use assembler::symbols::{Symbol, SymbolType};
 
   #[test]
   fn test_parse_instruction_ok() {
@@ -115,7 +320,7 @@ mod tests {
     let result = instruction(CompleteStr("load $1 #500"));
     assert!(result.is_ok());
     let (_, instr) = result.unwrap();
-    let bytes = instr.to_bytes();
+    let bytes = instr.to_bytes(&SymbolTable::new()).unwrap();
     assert_eq!(4, bytes.len());
     assert_eq!(0, bytes[0]);
     assert_eq!(1, bytes[1]);
@@ -179,4 +384,157 @@ mod tests {
       ins
     );
   }
+
+  #[test]
+  fn test_to_bytes_wide_load() {
+    let (_, instr) = instruction(CompleteStr("load $2 #70000")).unwrap();
+    assert_eq!(
+      vec![OpCode::LOAD as u8, 2, 0x11, 0x70, OpCode::LUI as u8, 2, 0x00, 0x01],
+      instr.to_bytes(&SymbolTable::new()).unwrap()
+    );
+
+    let (_, instr) = instruction(CompleteStr("load $2 #-1")).unwrap();
+    assert_eq!(
+      vec![OpCode::LOAD as u8, 2, 0xFF, 0xFF, OpCode::LUI as u8, 2, 0xFF, 0xFF],
+      instr.to_bytes(&SymbolTable::new()).unwrap()
+    );
+  }
+
+  #[test]
+  fn test_to_bytes_out_of_range() {
+    let (_, instr) = instruction(CompleteStr("load $2 #0x100000000")).unwrap();
+    assert_eq!(
+      Err(AssemblerError::IntegerOutOfRange {
+        value: 0x1_0000_0000,
+        min: i64::from(i32::MIN),
+        max: i64::from(u32::MAX)
+      }),
+      instr.to_bytes(&SymbolTable::new())
+    );
+
+    let (_, instr) = instruction(CompleteStr("lui $2 #70000")).unwrap();
+    assert_eq!(
+      Err(AssemblerError::IntegerOutOfRange { value: 70000, min: -32768, max: 65535 }),
+      instr.to_bytes(&SymbolTable::new())
+    );
+  }
+
+  #[test]
+  fn test_to_bytes_unknown_opcode() {
+    let (_, instr) = instruction(CompleteStr("lod $2 #1")).unwrap();
+    assert!(instr.to_bytes(&SymbolTable::new()).is_err());
+  }
+
+  #[test]
+  fn test_to_bytes_label_usage() {
+    let mut symbols = SymbolTable::new();
+    symbols.add_symbol(Symbol::new("target".to_string(), SymbolType::Label, 300));
+    let (_, instr) = instruction(CompleteStr("load $3 @target")).unwrap();
+    assert_eq!(vec![OpCode::LOAD as u8, 3, 1, 44], instr.to_bytes(&symbols).unwrap());
+    assert_eq!(
+      Err(AssemblerError::UndefinedLabel { name: "target".to_string() }),
+      instr.to_bytes(&SymbolTable::new())
+    );
+  }
+
+  #[test]
+  fn test_encoded_len() {
+    for source in ["hlt", "hlt $2", "load $0 #1", "load $0 #-1", "load $0 @somewhere", "add $0 $1 $2"].iter() {
+      let (_, instr) = instruction(CompleteStr(source)).unwrap();
+      let mut symbols = SymbolTable::new();
+      symbols.add_symbol(Symbol::new("somewhere".to_string(), SymbolType::Label, 0));
+      assert_eq!(instr.to_bytes(&symbols).unwrap().len(), instr.encoded_len(), "{}", source);
+    }
+  }
+
+  #[test]
+  fn test_to_bytes_operand_count() {
+    let (_, instr) = instruction(CompleteStr("add $0 $1")).unwrap();
+    assert_eq!(
+      Err(AssemblerError::WrongOperandCount { mnemonic: "add", min: 3, max: 3, got: 2 }),
+      instr.to_bytes(&SymbolTable::new())
+    );
+  }
+
+  #[test]
+  fn test_parse_label_only() {
+    let (rest, instr) = instruction(CompleteStr("loop:")).unwrap();
+    assert_eq!(CompleteStr(""), rest);
+    assert_eq!(Some("loop".to_string()), instr.label_name());
+    assert_eq!(Ok(vec![]), instr.to_bytes(&SymbolTable::new()));
+    assert_eq!(0, instr.encoded_len());
+  }
+
+  #[test]
+  fn test_to_bytes_uses_opcode_size() {
+    for &(source, len) in [("hlt", 1), ("hlt $3", 2), ("inc $0", 2), ("not $0 $1", 3), ("eq $0 $1", 4)].iter() {
+      let (_, instr) = instruction(CompleteStr(source)).unwrap();
+      assert_eq!(len, instr.to_bytes(&SymbolTable::new()).unwrap().len(), "{}", source);
+    }
+  }
+
+  #[test]
+  fn test_to_bytes_operand_kind() {
+    let (_, instr) = instruction(CompleteStr("load #1 $2")).unwrap();
+    assert_eq!(
+      Err(AssemblerError::WrongOperandKind {
+        mnemonic: "load",
+        position: 1,
+        expected: OperandKind::Register,
+        found: Token::IntegerOperand { value: 1 }
+      }),
+      instr.to_bytes(&SymbolTable::new())
+    );
+
+    let (_, instr) = instruction(CompleteStr("hlt #5 $3")).unwrap();
+    assert_eq!(
+      Err(AssemblerError::WrongOperandCount { mnemonic: "hlt", min: 0, max: 1, got: 2 }),
+      instr.to_bytes(&SymbolTable::new())
+    );
+
+    let (_, instr) = instruction(CompleteStr("hlt #5")).unwrap();
+    assert_eq!(
+      Err(AssemblerError::WrongOperandKind {
+        mnemonic: "hlt",
+        position: 1,
+        expected: OperandKind::Register,
+        found: Token::IntegerOperand { value: 5 }
+      }),
+      instr.to_bytes(&SymbolTable::new())
+    );
+
+    let (_, instr) = instruction(CompleteStr("inc $32")).unwrap();
+    assert_eq!(
+      Err(AssemblerError::RegisterOutOfRange { register: 32 }),
+      instr.to_bytes(&SymbolTable::new())
+    );
+  }
+
+  #[test]
+  fn test_data_directive_to_bytes() {
+    let cases: [(&str, Vec<u8>); 6] = [
+      (".asciiz \"ok\"", vec![b'o', b'k', 0]),
+      (".integer -2", vec![0xFF, 0xFF, 0xFF, 0xFE]),
+      (".byte 0xFF", vec![0xFF]),
+      (".space 3", vec![0, 0, 0]),
+      ("start: .data", vec![]),
+      (".code", vec![]),
+    ];
+    for (source, bytes) in cases.iter() {
+      let (_, instr) = instruction(CompleteStr(source)).unwrap();
+      assert_eq!(Ok(bytes.clone()), instr.to_bytes(&SymbolTable::new()), "{}", source);
+      assert_eq!(bytes.len(), instr.encoded_len(), "{}", source);
+    }
+
+    let (_, instr) = instruction(CompleteStr(".byte 256")).unwrap();
+    assert_eq!(
+      Err(AssemblerError::IntegerOutOfRange { value: 256, min: -128, max: 255 }),
+      instr.to_bytes(&SymbolTable::new())
+    );
+    let (_, instr) = instruction(CompleteStr(".space \"a\"")).unwrap();
+    assert_eq!(
+      Err(AssemblerError::WrongDirectiveOperands { name: "space".to_string(), expected: "a byte count" }),
+      instr.to_bytes(&SymbolTable::new())
+    );
+  }
 }
@@ -35,6 +35,7 @@ mod tests {
   use super::*;
   
   #[test]
+  #[allow(clippy::nonminimal_bool)]
   fn test_parse_label_declaration() {
     let result = label_declaration(CompleteStr("test:"));
     assert!(result.is_ok());
@@ -45,6 +46,7 @@ mod tests {
   }
   
   #[test]
+  #[allow(clippy::nonminimal_bool)]
   fn test_parse_label_usage() {
     let result = label_usage(CompleteStr("@test"));
     assert!(result.is_ok());
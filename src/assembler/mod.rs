@@ -5,15 +5,525 @@ pub mod instruction_parsers;
 pub mod program_parsers;
 pub mod directive_parsers;
 pub mod label_parsers;
+pub mod assembler_errors;
+pub mod symbols;
+pub mod diagnostics;
+
+use nom::types::CompleteStr;
+use std::fmt;
 
 use instruction::OpCode;
+use assembler::assembler_errors::AssemblerError;
+use assembler::diagnostics::{Diagnostic, Span};
+use assembler::instruction_parsers::{instruction, AssemblerInstruction};
+use assembler::symbols::{Symbol, SymbolTable, SymbolType};
+use bytecode::{Bytecode, DebugSymbol, SymbolSection};
+use std;
 
-#[derive(Debug,PartialEq)]
+#[derive(Debug,PartialEq,Clone)]
 pub enum Token {
   Op{code: OpCode},
   Register{reg_num: u8},
-  IntegerOperand{value: i32},
+  IntegerOperand{value: i64},
   LabelDeclaration{name: String},
   LabelUsage{name: String},
-  Directive{name: String}
+  Directive{name: String},
+  StringOperand{value: String}
+}
+
+impl fmt::Display for Token {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Token::Op{code} => write!(f, "{}", code.mnemonic()),
+      Token::Register{reg_num} => write!(f, "${}", reg_num),
+      Token::IntegerOperand{value} => write!(f, "#{}", value),
+      Token::LabelDeclaration{name} => write!(f, "{}:", name),
+      Token::LabelUsage{name} => write!(f, "@{}", name),
+      Token::Directive{name} => write!(f, ".{}", name),
+      Token::StringOperand{value} => write!(f, "{:?}", value),
+    }
+  }
+}
+
+/// Section of the program file assembled bytes are written to, switched with `.code` and `.data`
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Section {
+  Code,
+  Data,
+}
+
+impl fmt::Display for Section {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Section::Code => write!(f, "code"),
+      Section::Data => write!(f, "data"),
+    }
+  }
+}
+
+/// An instruction along with the source line it was parsed from
+#[derive(Debug)]
+struct SourceInstruction<'a> {
+  line: usize,
+  text: &'a str,
+  instruction: AssemblerInstruction,
+}
+
+/// Two pass assembler: the first pass records the offset of every label,
+/// the second one encodes instructions with label usages replaced by those offsets
+#[derive(Debug, Default)]
+pub struct Assembler {
+  pub symbols: SymbolTable,
+  /// Address the first assembled byte is loaded at, label offsets start from it
+  pub origin: usize,
+  /// Address of the first byte of the data section, data label offsets start from it
+  pub data_origin: usize,
+}
+
+impl Assembler {
+  pub fn new() -> Assembler {
+    Assembler {
+      symbols: SymbolTable::new(),
+      origin: 0,
+      data_origin: 0
+    }
+  }
+
+  /// Assembles `raw` into a program file, or returns a diagnostic for every problem found
+  pub fn assemble(&mut self, raw: &str) -> Result<Vec<u8>, Vec<Diagnostic>> {
+    self.assemble_bytecode(raw).map(|bytecode| bytecode.to_bytes())
+  }
+
+  /// Assembles `raw` and returns its code section alone, without the program file header
+  pub fn assemble_code(&mut self, raw: &str) -> Result<Vec<u8>, Vec<Diagnostic>> {
+    self.assemble_bytecode(raw).map(|bytecode| bytecode.code)
+  }
+
+  /// Assembles `raw` into its code and data sections
+  pub fn assemble_bytecode(&mut self, raw: &str) -> Result<Bytecode, Vec<Diagnostic>> {
+    let mut diagnostics = vec![];
+    let instructions = Assembler::parse_lines(raw, &mut diagnostics);
+
+    self.symbols = SymbolTable::new();
+    self.process_first_phase(&instructions, &mut diagnostics);
+    let mut bytecode = self.process_second_phase(&instructions, &mut diagnostics);
+    if diagnostics.is_empty() {
+      bytecode.symbols = self.debug_symbols();
+      Ok(bytecode)
+    } else {
+      diagnostics.sort_by_key(|diagnostic| (diagnostic.span.line, diagnostic.span.column));
+      Err(diagnostics)
+    }
+  }
+
+  /// The labels as program file symbols, so the VM can name addresses in backtraces
+  ///
+  /// Code symbols come first, then data symbols, each in address order, which is the order the
+  /// disassembler declares them in so that a disassembled program file assembles back the same.
+  fn debug_symbols(&self) -> Vec<DebugSymbol> {
+    let mut symbols: Vec<DebugSymbol> = self.symbols
+      .symbols()
+      .iter()
+      .map(|symbol| {
+        let section = match symbol.symbol_type {
+          SymbolType::Label => SymbolSection::Code,
+          SymbolType::Data => SymbolSection::Data,
+        };
+        DebugSymbol::new(symbol.name.clone(), section, symbol.offset)
+      })
+      .collect();
+    symbols.sort_by_key(|symbol| (symbol.section == SymbolSection::Data, symbol.offset));
+    symbols
+  }
+
+  /// Parses every line on its own so problems can be reported with their position
+  fn parse_lines<'a>(raw: &'a str, diagnostics: &mut Vec<Diagnostic>) -> Vec<SourceInstruction<'a>> {
+    let mut instructions = vec![];
+    for (index, text) in raw.lines().enumerate() {
+      let code = strip_comment(text);
+      if code.trim().is_empty() {
+        continue;
+      }
+      let code = code.trim_start();
+      let rest = match instruction(CompleteStr(code)) {
+        Ok((rest, instruction)) => {
+          if rest.trim().is_empty() {
+            instructions.push(SourceInstruction { line: index + 1, text, instruction });
+            continue;
+          }
+          rest.0
+        }
+        Err(_) => code
+      };
+      // both slices end where the comment starts, so their lengths locate `rest` in the line
+      let rest = rest.trim_start();
+      let start = strip_comment(text).len() - rest.len();
+      let rest = rest.trim_end();
+      let span = Span {
+        line: index + 1,
+        column: column_of(text, start),
+        len: std::cmp::max(1, rest.chars().count())
+      };
+      diagnostics.push(Diagnostic::new(AssemblerError::ParseError { rest: rest.to_string() }, span, text));
+    }
+    instructions
+  }
+
+  /// Builds the symbol table from the label declarations, each section keeping its own offset
+  fn process_first_phase(&mut self, instructions: &[SourceInstruction], diagnostics: &mut Vec<Diagnostic>) {
+    let mut section = Section::Code;
+    let mut code_offset = self.origin;
+    let mut data_offset = self.data_origin;
+    for source in instructions {
+      section = source.instruction.section().unwrap_or(section);
+      let (offset, symbol_type) = match section {
+        Section::Code => (&mut code_offset, SymbolType::Label),
+        Section::Data => (&mut data_offset, SymbolType::Data),
+      };
+      if let Some(name) = source.instruction.label_name() {
+        if self.symbols.has_symbol(&name) {
+          let span = field_span(source, 0);
+          diagnostics.push(Diagnostic::new(AssemblerError::DuplicateLabel { name }, span, source.text));
+        } else {
+          self.symbols.add_symbol(Symbol::new(name, symbol_type, *offset as u32));
+        }
+      }
+      *offset += source.instruction.encoded_len();
+    }
+  }
+
+  /// Encodes every line into its section, collecting all the errors instead of stopping at the first one
+  fn process_second_phase(&self, instructions: &[SourceInstruction], diagnostics: &mut Vec<Diagnostic>) -> Bytecode {
+    let mut bytecode = Bytecode::new(vec![], vec![]);
+    let mut section = Section::Code;
+    for source in instructions {
+      section = source.instruction.section().unwrap_or(section);
+      let result = source.instruction
+        .check_section(section)
+        .and_then(|_| source.instruction.to_bytes(&self.symbols));
+      match result {
+        Ok(mut bytes) => match section {
+          Section::Code => bytecode.code.append(&mut bytes),
+          Section::Data => bytecode.data.append(&mut bytes),
+        },
+        Err(error) => diagnostics.push(Assembler::diagnose(source, error))
+      }
+    }
+    bytecode
+  }
+
+  /// Points an error at the field of the source line that caused it
+  fn diagnose(source: &SourceInstruction, error: AssemblerError) -> Diagnostic {
+    let instruction = &source.instruction;
+    // fields are the optional label, then the opcode or directive, then the operands
+    let opcode_field = if instruction.is_label() { 1 } else { 0 };
+    let operand_field = |matches: &dyn Fn(&Token) -> bool| {
+      instruction.operands()
+        .iter()
+        .position(|operand| matches(operand))
+        .map_or(opcode_field, |index| opcode_field + 1 + index)
+    };
+    let (error, field) = match error {
+      AssemblerError::NonOpcodeInOpcodeField { .. } => {
+        let span = field_span(source, opcode_field);
+        let name = source.text.chars().skip(span.column - 1).take(span.len).collect();
+        (AssemblerError::UnknownMnemonic { name }, opcode_field)
+      }
+      AssemblerError::WrongOperandCount { max, got, .. } if got > max => {
+        (error, opcode_field + 1 + max)
+      }
+      AssemblerError::UndefinedLabel { ref name } => {
+        let field = operand_field(&|operand| match operand {
+          Token::LabelUsage { name: used } => used == name,
+          _ => false
+        });
+        (error.clone(), field)
+      }
+      AssemblerError::IntegerOutOfRange { value, .. } => {
+        let field = operand_field(&|operand| match operand {
+          Token::IntegerOperand { value: used } => *used == value,
+          _ => false
+        });
+        (error, field)
+      }
+      AssemblerError::WrongOperandKind { position, .. } => (error, opcode_field + position),
+      AssemblerError::RegisterOutOfRange { register } => {
+        let field = operand_field(&|operand| *operand == Token::Register { reg_num: register });
+        (error, field)
+      }
+      AssemblerError::UnexpectedOperand { ref token } => {
+        let field = operand_field(&|operand| operand == token);
+        (error.clone(), field)
+      }
+      _ => (error, opcode_field)
+    };
+    Diagnostic::new(error, field_span(source, field), source.text)
+  }
+}
+
+/// Drops everything from a `;` that is not inside a quoted literal
+fn strip_comment(line: &str) -> &str {
+  let mut quote = None;
+  let mut escaped = false;
+  for (index, c) in line.char_indices() {
+    match (quote, c) {
+      _ if escaped => escaped = false,
+      (Some(_), '\\') => escaped = true,
+      (Some(q), c) if c == q => quote = None,
+      (None, '\'') | (None, '"') => quote = Some(c),
+      (None, ';') => return &line[..index],
+      _ => {}
+    }
+  }
+  line
+}
+
+/// 1 based column of the byte at `offset` in `line`
+fn column_of(line: &str, offset: usize) -> usize {
+  line[..offset].chars().count() + 1
+}
+
+/// Span of the `index`th whitespace separated field of the line, or of its end when there is none
+fn field_span(source: &SourceInstruction, index: usize) -> Span {
+  let code = strip_comment(source.text);
+  let mut fields = vec![];
+  let mut start = None;
+  let mut quote = None;
+  for (offset, c) in code.char_indices() {
+    match (start, quote) {
+      (None, _) if !c.is_whitespace() => {
+        start = Some(offset);
+        if c == '"' {
+          quote = Some(c);
+        }
+      }
+      (Some(_), Some(q)) if c == q => quote = None,
+      (Some(_), None) if c == '\'' || c == '"' => quote = Some(c),
+      (Some(begin), None) if c.is_whitespace() => {
+        fields.push((begin, offset));
+        start = None;
+      }
+      _ => {}
+    }
+  }
+  if let Some(begin) = start {
+    fields.push((begin, code.trim_end().len()));
+  }
+  let (begin, end) = match fields.get(index) {
+    Some(&field) => field,
+    None => (code.trim_end().len(), code.trim_end().len())
+  };
+  Span {
+    line: source.line,
+    column: column_of(source.text, begin),
+    len: std::cmp::max(1, code[begin..end].chars().count())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use vm::{ExitReason, SharedBuffer, VM};
+
+  fn errors(result: Result<Vec<u8>, Vec<Diagnostic>>) -> Vec<AssemblerError> {
+    result.unwrap_err().into_iter().map(|diagnostic| diagnostic.error).collect()
+  }
+
+  #[test]
+  fn test_assemble_labels() {
+    let mut assembler = Assembler::new();
+    let source = "load $0 #3\nload $1 @loop\nloop: dec $0\njnz $1\nhlt\n";
+    let bytes = assembler.assemble_code(source).unwrap();
+    assert_eq!(Some(8), assembler.symbols.symbol_value("loop"));
+    assert_eq!(vec![OpCode::LOAD as u8, 1, 0, 8], bytes[4..8].to_vec());
+
+    let mut test_vm = VM::new();
+    test_vm.program = bytes;
+    assert_eq!(Ok(ExitReason::Halted { status: 0 }), test_vm.run());
+    assert_eq!(0, test_vm.registers[0]);
+  }
+
+  #[test]
+  fn test_forward_label() {
+    let mut assembler = Assembler::new();
+    let bytes = assembler.assemble_code("load $0 @end\njmp $0\nload $1 #1\nend:\n  hlt").unwrap();
+    assert_eq!(Some(10), assembler.symbols.symbol_value("end"));
+    assert_eq!(vec![OpCode::LOAD as u8, 0, 0, 10], bytes[0..4].to_vec());
+
+    let mut test_vm = VM::new();
+    test_vm.program = bytes;
+    assert_eq!(Ok(ExitReason::Halted { status: 0 }), test_vm.run());
+    assert_eq!(0, test_vm.registers[1]);
+  }
+
+  #[test]
+  fn test_origin() {
+    let mut assembler = Assembler::new();
+    assembler.origin = 100;
+    assembler.assemble("hlt\nhere: hlt").unwrap();
+    assert_eq!(Some(101), assembler.symbols.symbol_value("here"));
+  }
+
+  #[test]
+  fn test_undefined_label() {
+    let mut assembler = Assembler::new();
+    let result = assembler.assemble("load $0 @nowhere\nload $1 @elsewhere");
+    assert_eq!(
+      vec![
+        AssemblerError::UndefinedLabel { name: "nowhere".to_string() },
+        AssemblerError::UndefinedLabel { name: "elsewhere".to_string() }
+      ],
+      errors(result)
+    );
+  }
+
+  #[test]
+  fn test_duplicate_label() {
+    let mut assembler = Assembler::new();
+    let result = assembler.assemble("twice: hlt\ntwice: hlt");
+    assert_eq!(vec![AssemblerError::DuplicateLabel { name: "twice".to_string() }], errors(result));
+  }
+
+  #[test]
+  fn test_comments() {
+    let mut assembler = Assembler::new();
+    let bytes = assembler.assemble_code("; a comment\nload $0 #';' ; the semicolon\n\n").unwrap();
+    assert_eq!(vec![OpCode::LOAD as u8, 0, 0, b';'], bytes);
+  }
+
+  #[test]
+  fn test_diagnostic_spans() {
+    let mut assembler = Assembler::new();
+    let source = "load $0 #1\n  lod $0 #1\nadd $0 $1\nstart: load $2 @nowhere\nload $0 #99999999999\nhlt $1 $2\nload #1 $2\nload $0 %%\n";
+    let diagnostics = assembler.assemble(source).unwrap_err();
+    let found: Vec<(usize, usize, usize, &str)> = diagnostics
+      .iter()
+      .map(|d| (d.span.line, d.span.column, d.span.len, d.message.as_str()))
+      .collect();
+    assert_eq!(
+      vec![
+        (2, 3, 3, "unknown mnemonic `lod`"),
+        (3, 1, 3, "`add` expects 3 operands, got 2"),
+        (4, 16, 8, "undefined label `nowhere`"),
+        (5, 9, 12, "integer 99999999999 is out of range, expected -2147483648 to 4294967295"),
+        (6, 8, 2, "`hlt` expects 0 or 1 operands, got 2"),
+        (7, 6, 2, "`load` operand 1 must be a register, found `#1`"),
+        (8, 9, 2, "unexpected `%%`"),
+      ],
+      found
+    );
+    assert_eq!("  |\n2 |   lod $0 #1\n  |   ^^^", diagnostics[0].excerpt());
+  }
+
+  #[test]
+  fn test_data_section() {
+    let mut assembler = Assembler::new();
+    let source = ".data\nhello: .asciiz \"hi\"\ntable: .integer 0x01020304\n.byte -1\n.space 2\n\
+                  .code\nload $0 @hello\nload $1 @table\nhlt";
+    let bytes = assembler.assemble(source).unwrap();
+    assert_eq!(Some(0), assembler.symbols.symbol_value("hello"));
+    assert_eq!(Some(3), assembler.symbols.symbol_value("table"));
+
+    let mut test_vm = VM::new();
+    test_vm.load_program(&bytes).unwrap();
+    assert_eq!(vec![b'h', b'i', 0, 1, 2, 3, 4, 0xFF, 0, 0], test_vm.ro_data);
+    assert_eq!(Ok(ExitReason::Halted { status: 0 }), test_vm.run());
+    assert_eq!([0, 3], test_vm.registers[0..2]);
+  }
+
+  #[test]
+  fn test_read_data_section() {
+    let mut assembler = Assembler::new();
+    let source = ".data\nword: .integer -5\n.code\nload $0 @word\nlw $1 $0 #0\nlbu $2 $0 #3\nlb $3 $0 #3";
+    let bytes = assembler.assemble(source).unwrap();
+    let mut test_vm = VM::new();
+    test_vm.load_program(&bytes).unwrap();
+    assert_eq!(Ok(ExitReason::EndOfProgram), test_vm.run());
+    assert_eq!([-5, 0xFB, -5], test_vm.registers[1..4]);
+  }
+
+  #[test]
+  fn test_data_labels_in_code() {
+    let mut assembler = Assembler::new();
+    assembler.data_origin = 10;
+    let source = "load $0 @jump\n.data\njump: .integer @target\n.code\ntarget: hlt";
+    let bytecode = assembler.assemble_bytecode(source).unwrap();
+    assert_eq!(Some(10), assembler.symbols.symbol_value("jump"));
+    assert_eq!(vec![OpCode::LOAD as u8, 0, 0, 10, OpCode::HLT as u8], bytecode.code);
+    assert_eq!(vec![0, 0, 0, 4], bytecode.data);
+  }
+
+  #[test]
+  fn test_wrong_section() {
+    let mut assembler = Assembler::new();
+    let result = assembler.assemble(".byte 1\n.data\nhlt\n.asciiz 7\n.text");
+    assert_eq!(
+      vec![
+        AssemblerError::WrongSection { token: Token::Directive { name: "byte".to_string() }, section: Section::Code },
+        AssemblerError::WrongSection { token: Token::Op { code: OpCode::HLT }, section: Section::Data },
+        AssemblerError::WrongDirectiveOperands { name: "asciiz".to_string(), expected: "a string" },
+        AssemblerError::UnknownDirective { name: "text".to_string() },
+      ],
+      errors(result)
+    );
+  }
+
+  #[test]
+  fn test_subroutine() {
+    let mut assembler = Assembler::new();
+    let source = "load $0 #3\ncall @quadruple\ncall @quadruple\nhlt\n\
+                  quadruple: push $1\nadd $0 $0 $1\nadd $1 $1 $0\npop $1\nret";
+    let bytes = assembler.assemble(source).unwrap();
+    let mut test_vm = VM::new();
+    test_vm.load_program(&bytes).unwrap();
+    assert_eq!(Ok(ExitReason::Halted { status: 0 }), test_vm.run());
+    assert_eq!([48, 0], test_vm.registers[0..2]);
+  }
+
+  #[test]
+  fn test_backtrace_names_labels() {
+    let mut assembler = Assembler::new();
+    let source = "call @outer\nhlt\nouter: call @inner\nret\ninner: div $0 $0 $0\nret";
+    let bytes = assembler.assemble(source).unwrap();
+    let mut test_vm = VM::new();
+    test_vm.load_program(&bytes).unwrap();
+    let error = test_vm.run().unwrap_err();
+    assert_eq!(
+      "backtrace:\n  0: returns to 7 (outer+3)\n  1: returns to 3",
+      error.backtrace.to_string()
+    );
+  }
+
+  #[test]
+  fn test_console_program() {
+    let mut assembler = Assembler::new();
+    let source = ".data\nprompt: .asciiz \"twice \"\n.code\n\
+                  load $0 @prompt\nprts $0\nreadi $1\nadd $1 $1 $1\nprti $1\nload $2 #'\\n'\nprtc $2\nhlt";
+    let bytes = assembler.assemble(source).unwrap();
+    let output = SharedBuffer::new();
+    let mut test_vm = VM::new().with_output(output.clone()).with_input(&b"21\n"[..]);
+    test_vm.load_program(&bytes).unwrap();
+    assert_eq!(Ok(ExitReason::Halted { status: 0 }), test_vm.run());
+    assert_eq!("twice 42\n", output.to_string_lossy());
+  }
+
+  #[test]
+  fn test_hlt_with_status() {
+    let mut assembler = Assembler::new();
+    let bytes = assembler.assemble_code("load $4 #3\nhlt $4").unwrap();
+    assert_eq!(vec![OpCode::EXIT as u8, 4], bytes[4..].to_vec());
+    let mut test_vm = VM::new();
+    test_vm.program = bytes;
+    assert_eq!(Ok(ExitReason::Halted { status: 3 }), test_vm.run());
+  }
+
+  #[test]
+  fn test_assemble_program_file() {
+    let mut assembler = Assembler::new();
+    let bytes = assembler.assemble("load $0 #7\nhlt").unwrap();
+    let mut test_vm = VM::new();
+    test_vm.load_program(&bytes).unwrap();
+    assert_eq!(Ok(ExitReason::Halted { status: 0 }), test_vm.run());
+    assert_eq!(7, test_vm.registers[0]);
+  }
 }
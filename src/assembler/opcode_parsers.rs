@@ -16,12 +16,14 @@ mod tests {
   use super::*;
 
   #[test]
+  #[allow(clippy::nonminimal_bool)]
   fn test_op_load_nok() {
     let result = opcode(CompleteStr("1load1"));
     assert!(!result.is_ok());
   }
   
   #[test]
+  #[allow(clippy::bool_assert_comparison)]
   fn test_op_load_ok() {
     let result = opcode(CompleteStr("load"));
     assert_eq!(result.is_ok(), true);
@@ -1,22 +1,90 @@
 use assembler::Token;
 use assembler::register_parsers::register;
-use nom::digit;
+use assembler::label_parsers::label_usage;
+use nom::{digit, hex_digit};
 use nom::types::CompleteStr;
 
+named!(hex_literal<CompleteStr, i64>,
+  preceded!(
+    tag_no_case!("0x"),
+    map_res!(hex_digit, |d: CompleteStr| i64::from_str_radix(&d, 16))
+  )
+);
+
+named!(bin_literal<CompleteStr, i64>,
+  preceded!(
+    tag_no_case!("0b"),
+    map_res!(is_a!("01"), |d: CompleteStr| i64::from_str_radix(&d, 2))
+  )
+);
+
+named!(dec_literal<CompleteStr, i64>,
+  map_res!(digit, |d: CompleteStr| d.parse::<i64>())
+);
+
+// -42, 0x2A, -0b101010
+named!(pub signed_literal<CompleteStr, i64>,
+  do_parse!(
+    negative: opt!(tag!("-")) >>
+    magnitude: alt!(hex_literal | bin_literal | dec_literal) >>
+    (
+      if negative.is_some() { -magnitude } else { magnitude }
+    )
+  )
+);
+
+named!(escaped_char<CompleteStr, char>,
+  preceded!(
+    tag!("\\"),
+    map!(one_of!("nrt0\\'\""), |c| match c {
+      'n' => '\n',
+      'r' => '\r',
+      't' => '\t',
+      '0' => '\0',
+      c => c
+    })
+  )
+);
+
+// 'a', '\n'
+named!(pub char_literal<CompleteStr, i64>,
+  delimited!(
+    tag!("'"),
+    map!(alt!(escaped_char | none_of!("'\\")), |c| c as i64),
+    tag!("'")
+  )
+);
+
 named!(pub int_operand<CompleteStr,Token>,
   ws!(
     do_parse!(
       tag!("#") >>
-      operand: digit >> (
-        Token::IntegerOperand{ value : operand.parse::<i32>().unwrap()}
+      value: alt!(char_literal | signed_literal) >> (
+        Token::IntegerOperand{ value }
       )
     )
   )
 );
 
+// the quotes and the characters are not separated by `ws!`, spaces inside are part of the string
+named!(string_literal<CompleteStr, String>,
+  delimited!(
+    tag!("\""),
+    map!(many0!(alt!(escaped_char | none_of!("\"\\"))), |chars| chars.into_iter().collect()),
+    tag!("\"")
+  )
+);
+
+// "hello, world\n"
+named!(pub string_operand<CompleteStr, Token>,
+  ws!(
+    map!(string_literal, |value| Token::StringOperand { value })
+  )
+);
+
 named!(pub operand<CompleteStr, Token>,
   alt!(
-    int_operand | register
+    int_operand | register | label_usage | string_operand
   )
 );
 
@@ -25,14 +93,18 @@ mod tests {
   use super::*;
 
   #[test]
+  #[allow(clippy::nonminimal_bool)]
   fn test_parse_int_operand_nok() {
     let result = int_operand(CompleteStr("100"));
     assert!(!result.is_ok());
 
 		let result = int_operand(CompleteStr("#abc"));
     assert!(!result.is_ok());
+
+    let result = int_operand(CompleteStr("#'ab'"));
+    assert!(result.is_err());
   }
-  
+
   #[test]
   fn test_parse_int_operand_ok() {
     let result = int_operand(CompleteStr("#200"));
@@ -41,5 +113,38 @@ mod tests {
     assert_eq!(CompleteStr(""), rest);
     assert_eq!(Token::IntegerOperand{value: 200}, token);
   }
-  
-}
\ No newline at end of file
+
+  #[test]
+  fn test_parse_int_operand_literals() {
+    let cases = [
+      ("#-1", -1),
+      ("#70000", 70000),
+      ("#0x1F", 31),
+      ("#-0XfF", -255),
+      ("#0b1010", 10),
+      ("#'a'", 97),
+      ("#'\\n'", 10),
+      ("#'\\''", 39),
+    ];
+    for &(input, value) in cases.iter() {
+      let result = int_operand(CompleteStr(input));
+      assert_eq!(Ok((CompleteStr(""), Token::IntegerOperand{value})), result, "{}", input);
+    }
+  }
+
+  #[test]
+  fn test_parse_string_operand() {
+    let cases = [
+      ("\"hello, world\"", "hello, world"),
+      ("\"\"", ""),
+      ("\"say \\\"hi\\\"\\n\"", "say \"hi\"\n"),
+      ("\"  padded \"", "  padded "),
+    ];
+    for &(input, value) in cases.iter() {
+      let result = string_operand(CompleteStr(input));
+      assert_eq!(Ok((CompleteStr(""), Token::StringOperand{value: value.to_string()})), result, "{}", input);
+    }
+    assert!(string_operand(CompleteStr("\"unterminated")).is_err());
+  }
+
+}
@@ -1,24 +1,46 @@
 use nom::types::CompleteStr;
 use assembler::instruction_parsers::*;
+use assembler::assembler_errors::AssemblerError;
+use assembler::symbols::SymbolTable;
+use assembler::Section;
+use bytecode::Bytecode;
 
 #[derive(Debug, PartialEq)]
 pub struct Program{
-  instructions: Vec<AssemblerInstruction>
+  pub instructions: Vec<AssemblerInstruction>
 }
 
 impl Program{
-  pub fn to_bytes(&self) -> Vec<u8> {
-    let mut program = vec![];
+  /// Program file holding the assembled instructions, see `bytecode` for the layout
+  pub fn to_bytes(&self, symbols: &SymbolTable) -> Result<Vec<u8>, AssemblerError> {
+    Ok(self.to_bytecode(symbols)?.to_bytes())
+  }
+
+  /// The code section alone, without a program file header
+  pub fn code_bytes(&self, symbols: &SymbolTable) -> Result<Vec<u8>, AssemblerError> {
+    Ok(self.to_bytecode(symbols)?.code)
+  }
+
+  /// Assembles every instruction into the section it appears in
+  pub fn to_bytecode(&self, symbols: &SymbolTable) -> Result<Bytecode, AssemblerError> {
+    let mut bytecode = Bytecode::new(vec![], vec![]);
+    let mut section = Section::Code;
     for instruction in &self.instructions {
-      program.append(&mut instruction.to_bytes());
+      section = instruction.section().unwrap_or(section);
+      instruction.check_section(section)?;
+      let mut bytes = instruction.to_bytes(symbols)?;
+      match section {
+        Section::Code => bytecode.code.append(&mut bytes),
+        Section::Data => bytecode.data.append(&mut bytes),
+      }
     }
-    program
+    Ok(bytecode)
   }
 }
 
 named!(pub program<CompleteStr, Program>,
   do_parse!(
-    instructions: many1!(instruction) >> (
+    instructions: many1!(ws!(instruction)) >> (
       Program{instructions}
     )
   )
@@ -27,6 +49,7 @@ named!(pub program<CompleteStr, Program>,
 #[cfg(test)]
 mod tests {
   use super::*;
+  use instruction::OpCode;
   
   #[test]
   fn test_parse_program_ok() {
@@ -41,7 +64,7 @@ mod tests {
       let result = program(CompleteStr("load $1 #500"));
       assert!(result.is_ok());
       let (_, prog) = result.unwrap();
-      let bytes = prog.to_bytes();
+      let bytes = prog.code_bytes(&SymbolTable::new()).unwrap();
       assert_eq!(4, bytes.len());
       assert_eq!(0, bytes[0]);
       assert_eq!(1, bytes[1]);
@@ -49,4 +72,32 @@ mod tests {
       assert_eq!(1, bytes[2]);
       assert_eq!(244, bytes[3]);
   }
-}
\ No newline at end of file
+
+  #[test]
+  fn test_parse_multiline_program() {
+    let result = program(CompleteStr("  load $0 #1\nhlt\n\nloop:\n  inc $0\n  hlt  \n"));
+    assert!(result.is_ok());
+    let (rest, prog) = result.unwrap();
+    assert_eq!(CompleteStr(""), rest);
+    assert_eq!(4, prog.instructions.len());
+    assert_eq!(Some("loop".to_string()), prog.instructions[2].label_name());
+  }
+
+  #[test]
+  fn test_program_to_program_file() {
+    let (_, prog) = program(CompleteStr("load $1 #500")).unwrap();
+    let bytes = prog.to_bytes(&SymbolTable::new()).unwrap();
+    let bytecode = Bytecode::from_bytes(&bytes).unwrap();
+    assert_eq!(prog.code_bytes(&SymbolTable::new()).unwrap(), bytecode.code);
+    assert_eq!(0, bytecode.entry_point);
+    assert!(bytecode.data.is_empty());
+  }
+
+  #[test]
+  fn test_program_sections() {
+    let (_, prog) = program(CompleteStr(".data\n.byte 7\n.code\nhlt\n.data\n.byte 8")).unwrap();
+    let bytecode = prog.to_bytecode(&SymbolTable::new()).unwrap();
+    assert_eq!(vec![OpCode::HLT as u8], bytecode.code);
+    assert_eq!(vec![7, 8], bytecode.data);
+  }
+}
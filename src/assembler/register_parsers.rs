@@ -8,9 +8,9 @@ named!{pub register<CompleteStr, Token>,
   ws!(
     do_parse!(
       tag!("$") >>
-      reg_num: digit >> (
+      reg_num: map_res!(digit, |d: CompleteStr| d.parse::<u8>()) >> (
         Token::Register {
-          reg_num : reg_num.parse::<u8>().unwrap()
+          reg_num
         }
       )
     )
@@ -22,11 +22,14 @@ mod tests {
   use super::*;
 
   #[test]
+  #[allow(clippy::nonminimal_bool)]
   fn test_register_nok() {
     let result = register(CompleteStr("20"));
     assert!(!result.is_ok());
     let result = register(CompleteStr("$a"));
     assert!(!result.is_ok());
+    let result = register(CompleteStr("$300"));
+    assert!(result.is_err());
   }
 
   #[test]
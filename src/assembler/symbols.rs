@@ -0,0 +1,69 @@
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SymbolType {
+  /// Offset of an instruction in the code section
+  Label,
+  /// Offset of a value in the read-only data section
+  Data,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Symbol {
+  pub name: String,
+  pub offset: u32,
+  pub symbol_type: SymbolType,
+}
+
+impl Symbol {
+  pub fn new(name: String, symbol_type: SymbolType, offset: u32) -> Symbol {
+    Symbol {
+      name,
+      offset,
+      symbol_type
+    }
+  }
+}
+
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct SymbolTable {
+  symbols: Vec<Symbol>,
+}
+
+impl SymbolTable {
+  pub fn new() -> SymbolTable {
+    SymbolTable { symbols: vec![] }
+  }
+
+  pub fn add_symbol(&mut self, symbol: Symbol) {
+    self.symbols.push(symbol);
+  }
+
+  pub fn has_symbol(&self, name: &str) -> bool {
+    self.symbols.iter().any(|symbol| symbol.name == name)
+  }
+
+  /// Offset of the symbol called `name`, if it was declared
+  pub fn symbol_value(&self, name: &str) -> Option<u32> {
+    self.symbols
+      .iter()
+      .find(|symbol| symbol.name == name)
+      .map(|symbol| symbol.offset)
+  }
+
+  pub fn symbols(&self) -> &[Symbol] {
+    &self.symbols
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_symbol_table() {
+    let mut table = SymbolTable::new();
+    table.add_symbol(Symbol::new("test".to_string(), SymbolType::Label, 12));
+    assert!(table.has_symbol("test"));
+    assert_eq!(Some(12), table.symbol_value("test"));
+    assert_eq!(None, table.symbol_value("does_not_exist"));
+  }
+}
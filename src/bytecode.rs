@@ -0,0 +1,358 @@
+//! Container format of assembled programs
+//!
+//! All integers are big endian, like the operands in the bytecode itself.
+//!
+//! | Offset | Size | Field                                           |
+//! |--------|------|-------------------------------------------------|
+//! | 0      | 4    | magic number, `IRDM`                            |
+//! | 4      | 2    | format version, currently 2                     |
+//! | 6      | 2    | instruction set revision the code was built for |
+//! | 8      | 4    | entry point, offset into the code section       |
+//! | 12     | 4    | length of the code section                      |
+//! | 16     | 4    | length of the read-only data section            |
+//! | 20     | 4    | length of the symbol section                    |
+//! | 24     | ...  | code section, read-only data, then symbols      |
+//!
+//! The symbol section is debug information, the VM runs a program the same without it.
+//! Each symbol is the offset it names as 4 bytes, the section it points into as 1 byte,
+//! 0 for code and 1 for data, then its name as a 2 byte length followed by UTF-8.
+//!
+//! Version 1 files have a 20 byte header without the symbol section length, and no symbols.
+
+use core::error::Error;
+use core::fmt;
+
+use instruction::{INSTRUCTION_SET_REVISION, MIN_INSTRUCTION_SET_REVISION};
+use prelude::*;
+
+pub const MAGIC: [u8; 4] = *b"IRDM";
+pub const FORMAT_VERSION: u16 = 2;
+pub const HEADER_LEN: usize = 24;
+/// Header length of version 1 files
+const V1_HEADER_LEN: usize = 20;
+
+/// Why a byte buffer was rejected as a program
+#[derive(Debug, PartialEq, Clone)]
+pub enum LoadError {
+  /// The buffer does not start with `MAGIC`
+  BadMagic,
+  /// The format version is not one this VM can read
+  UnsupportedVersion { version: u16 },
+  /// The code was built for a newer instruction set than this VM implements
+  UnsupportedInstructionSet { revision: u16 },
+  /// The code was built for an instruction set revision whose encoding has since changed
+  OutdatedInstructionSet { revision: u16 },
+  /// The buffer is shorter than its header says
+  Truncated { expected: usize, found: usize },
+  /// The entry point lies outside of the code section
+  EntryPointOutOfRange { entry_point: u32 },
+  /// The symbol section cannot be decoded
+  MalformedSymbols,
+}
+
+impl fmt::Display for LoadError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      LoadError::BadMagic => write!(f, "not a program file, bad magic number"),
+      LoadError::UnsupportedVersion { version } => {
+        write!(f, "unsupported format version {}, expected {}", version, FORMAT_VERSION)
+      }
+      LoadError::UnsupportedInstructionSet { revision } => write!(
+        f,
+        "program needs instruction set revision {}, this VM implements {}",
+        revision, INSTRUCTION_SET_REVISION
+      ),
+      LoadError::OutdatedInstructionSet { revision } => write!(
+        f,
+        "program was built for instruction set revision {}, this VM runs revision {} and later",
+        revision, MIN_INSTRUCTION_SET_REVISION
+      ),
+      LoadError::Truncated { expected, found } => {
+        write!(f, "program file is truncated, expected {} bytes, found {}", expected, found)
+      }
+      LoadError::EntryPointOutOfRange { entry_point } => {
+        write!(f, "entry point {} is outside of the code section", entry_point)
+      }
+      LoadError::MalformedSymbols => write!(f, "malformed symbol section"),
+    }
+  }
+}
+
+impl Error for LoadError {}
+
+/// Section a debug symbol points into
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SymbolSection {
+  Code,
+  Data,
+}
+
+/// Name of an offset in the code or data section, kept to make addresses readable
+#[derive(Debug, PartialEq, Clone)]
+pub struct DebugSymbol {
+  pub name: String,
+  pub offset: u32,
+  pub section: SymbolSection,
+}
+
+impl DebugSymbol {
+  pub fn new(name: String, section: SymbolSection, offset: u32) -> DebugSymbol {
+    DebugSymbol { name, offset, section }
+  }
+}
+
+/// The sections of a program file
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Bytecode {
+  pub revision: u16,
+  pub entry_point: u32,
+  pub code: Vec<u8>,
+  pub data: Vec<u8>,
+  pub symbols: Vec<DebugSymbol>,
+}
+
+impl Bytecode {
+  /// Wraps code and data built for the current instruction set revision
+  pub fn new(code: Vec<u8>, data: Vec<u8>) -> Bytecode {
+    Bytecode {
+      revision: INSTRUCTION_SET_REVISION,
+      entry_point: 0,
+      code,
+      data,
+      symbols: vec![]
+    }
+  }
+
+  pub fn to_bytes(&self) -> Vec<u8> {
+    let mut symbols = vec![];
+    for symbol in &self.symbols {
+      symbols.extend_from_slice(&symbol.offset.to_be_bytes());
+      symbols.push(match symbol.section {
+        SymbolSection::Code => 0,
+        SymbolSection::Data => 1,
+      });
+      symbols.extend_from_slice(&(symbol.name.len() as u16).to_be_bytes());
+      symbols.extend_from_slice(symbol.name.as_bytes());
+    }
+    let mut bytes = Vec::with_capacity(HEADER_LEN + self.code.len() + self.data.len() + symbols.len());
+    bytes.extend_from_slice(&MAGIC);
+    bytes.extend_from_slice(&FORMAT_VERSION.to_be_bytes());
+    bytes.extend_from_slice(&self.revision.to_be_bytes());
+    bytes.extend_from_slice(&self.entry_point.to_be_bytes());
+    bytes.extend_from_slice(&(self.code.len() as u32).to_be_bytes());
+    bytes.extend_from_slice(&(self.data.len() as u32).to_be_bytes());
+    bytes.extend_from_slice(&(symbols.len() as u32).to_be_bytes());
+    bytes.extend_from_slice(&self.code);
+    bytes.extend_from_slice(&self.data);
+    bytes.extend_from_slice(&symbols);
+    bytes
+  }
+
+  /// Checks the header and splits the sections out of `bytes`
+  pub fn from_bytes(bytes: &[u8]) -> Result<Bytecode, LoadError> {
+    if bytes.len() < MAGIC.len() || bytes[..MAGIC.len()] != MAGIC {
+      return Err(LoadError::BadMagic);
+    }
+    if bytes.len() < 6 {
+      return Err(LoadError::Truncated { expected: HEADER_LEN, found: bytes.len() });
+    }
+    let version = read_u16(bytes, 4);
+    let header_len = match version {
+      1 => V1_HEADER_LEN,
+      FORMAT_VERSION => HEADER_LEN,
+      _ => return Err(LoadError::UnsupportedVersion { version })
+    };
+    if bytes.len() < header_len {
+      return Err(LoadError::Truncated { expected: header_len, found: bytes.len() });
+    }
+    let revision = read_u16(bytes, 6);
+    if revision > INSTRUCTION_SET_REVISION {
+      return Err(LoadError::UnsupportedInstructionSet { revision });
+    }
+    if revision < MIN_INSTRUCTION_SET_REVISION {
+      return Err(LoadError::OutdatedInstructionSet { revision });
+    }
+    let entry_point = read_u32(bytes, 8);
+    let code_len = read_u32(bytes, 12) as usize;
+    let data_len = read_u32(bytes, 16) as usize;
+    let symbols_len = if version == 1 { 0 } else { read_u32(bytes, 20) as usize };
+    let expected = header_len
+      .checked_add(code_len)
+      .and_then(|len| len.checked_add(data_len))
+      .and_then(|len| len.checked_add(symbols_len))
+      .unwrap_or(usize::MAX);
+    if bytes.len() < expected {
+      return Err(LoadError::Truncated { expected, found: bytes.len() });
+    }
+    // an entry point equal to the code length is an empty program
+    if entry_point as usize > code_len {
+      return Err(LoadError::EntryPointOutOfRange { entry_point });
+    }
+    let code_end = header_len + code_len;
+    let data_end = code_end + data_len;
+    Ok(Bytecode {
+      revision,
+      entry_point,
+      code: bytes[header_len..code_end].to_vec(),
+      data: bytes[code_end..data_end].to_vec(),
+      symbols: read_symbols(&bytes[data_end..expected])?
+    })
+  }
+
+  /// The code symbol `address` falls under, with the distance from it
+  pub fn symbolize(symbols: &[DebugSymbol], address: usize) -> Option<(&str, usize)> {
+    symbols
+      .iter()
+      .filter(|symbol| symbol.section == SymbolSection::Code && symbol.offset as usize <= address)
+      .max_by_key(|symbol| symbol.offset)
+      .map(|symbol| (symbol.name.as_str(), address - symbol.offset as usize))
+  }
+}
+
+fn read_symbols(mut bytes: &[u8]) -> Result<Vec<DebugSymbol>, LoadError> {
+  let mut symbols = vec![];
+  while !bytes.is_empty() {
+    if bytes.len() < 7 {
+      return Err(LoadError::MalformedSymbols);
+    }
+    let offset = read_u32(bytes, 0);
+    let section = match bytes[4] {
+      0 => SymbolSection::Code,
+      1 => SymbolSection::Data,
+      _ => return Err(LoadError::MalformedSymbols)
+    };
+    let name_end = 7 + read_u16(bytes, 5) as usize;
+    let name = bytes.get(7..name_end).ok_or(LoadError::MalformedSymbols)?;
+    let name = String::from_utf8(name.to_vec()).map_err(|_| LoadError::MalformedSymbols)?;
+    symbols.push(DebugSymbol::new(name, section, offset));
+    bytes = &bytes[name_end..];
+  }
+  Ok(symbols)
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+  (u16::from(bytes[offset]) << 8) | u16::from(bytes[offset + 1])
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+  (u32::from(read_u16(bytes, offset)) << 16) | u32::from(read_u16(bytes, offset + 2))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn get_bytecode() -> Bytecode {
+    Bytecode {
+      revision: INSTRUCTION_SET_REVISION,
+      entry_point: 1,
+      code: vec![5, 5],
+      data: vec![1, 2, 3],
+      symbols: vec![
+        DebugSymbol::new("start".to_string(), SymbolSection::Code, 0),
+        DebugSymbol::new("table".to_string(), SymbolSection::Data, 1),
+      ]
+    }
+  }
+
+  #[test]
+  fn test_round_trip() {
+    let bytecode = get_bytecode();
+    let bytes = bytecode.to_bytes();
+    assert_eq!(HEADER_LEN + 5 + 24, bytes.len());
+    assert_eq!(&MAGIC, &bytes[0..4]);
+    assert_eq!(Ok(bytecode), Bytecode::from_bytes(&bytes));
+  }
+
+  #[test]
+  fn test_bad_magic() {
+    assert_eq!(Err(LoadError::BadMagic), Bytecode::from_bytes(&[5, 0, 0, 0]));
+    assert_eq!(Err(LoadError::BadMagic), Bytecode::from_bytes(&[]));
+  }
+
+  #[test]
+  fn test_unsupported_version() {
+    let mut bytes = get_bytecode().to_bytes();
+    bytes[5] = 3;
+    assert_eq!(Err(LoadError::UnsupportedVersion { version: 3 }), Bytecode::from_bytes(&bytes));
+  }
+
+  #[test]
+  fn test_unsupported_instruction_set() {
+    let mut bytecode = get_bytecode();
+    bytecode.revision = INSTRUCTION_SET_REVISION + 1;
+    assert_eq!(
+      Err(LoadError::UnsupportedInstructionSet { revision: INSTRUCTION_SET_REVISION + 1 }),
+      Bytecode::from_bytes(&bytecode.to_bytes())
+    );
+  }
+
+  #[test]
+  fn test_outdated_instruction_set() {
+    let mut bytecode = get_bytecode();
+    bytecode.revision = MIN_INSTRUCTION_SET_REVISION - 1;
+    assert_eq!(
+      Err(LoadError::OutdatedInstructionSet { revision: MIN_INSTRUCTION_SET_REVISION - 1 }),
+      Bytecode::from_bytes(&bytecode.to_bytes())
+    );
+  }
+
+  #[test]
+  fn test_truncated() {
+    let bytes = get_bytecode().to_bytes();
+    assert_eq!(
+      Err(LoadError::Truncated { expected: HEADER_LEN + 29, found: HEADER_LEN + 28 }),
+      Bytecode::from_bytes(&bytes[..bytes.len() - 1])
+    );
+    assert_eq!(
+      Err(LoadError::Truncated { expected: HEADER_LEN, found: 10 }),
+      Bytecode::from_bytes(&bytes[..10])
+    );
+  }
+
+  #[test]
+  fn test_section_lengths_overflow() {
+    let mut bytes = get_bytecode().to_bytes();
+    for byte in &mut bytes[12..24] {
+      *byte = 0xff;
+    }
+    // the sum overflows on 32 bit targets, where it is reported as usize::MAX
+    assert!(matches!(Bytecode::from_bytes(&bytes), Err(LoadError::Truncated { .. })));
+  }
+
+  #[test]
+  fn test_entry_point_out_of_range() {
+    let mut bytecode = get_bytecode();
+    bytecode.entry_point = 3;
+    assert_eq!(
+      Err(LoadError::EntryPointOutOfRange { entry_point: 3 }),
+      Bytecode::from_bytes(&bytecode.to_bytes())
+    );
+  }
+
+  #[test]
+  fn test_version_1() {
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend_from_slice(&[0, 1, 0, 3, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 1, 5, 5, 9]);
+    let bytecode = Bytecode::from_bytes(&bytes).unwrap();
+    assert_eq!(vec![5, 5], bytecode.code);
+    assert_eq!(vec![9], bytecode.data);
+    assert!(bytecode.symbols.is_empty());
+  }
+
+  #[test]
+  fn test_malformed_symbols() {
+    let mut bytes = get_bytecode().to_bytes();
+    // the last name is one byte short of the length it announces
+    let len = bytes.len();
+    bytes[len - 6] = 6;
+    assert_eq!(Err(LoadError::MalformedSymbols), Bytecode::from_bytes(&bytes));
+  }
+
+  #[test]
+  fn test_symbolize() {
+    let symbols = get_bytecode().symbols;
+    assert_eq!(Some(("start", 3)), Bytecode::symbolize(&symbols, 3));
+    assert_eq!(None, Bytecode::symbolize(&symbols[1..], 3));
+  }
+}
@@ -0,0 +1,288 @@
+//! Command line driver, see `USAGE`
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use rust_vm::assembler::Assembler;
+use rust_vm::bytecode::{Bytecode, MAGIC};
+use rust_vm::disassembler;
+use rust_vm::vm::{ExitReason, Location, VM, VmError};
+
+use repl::REPL;
+
+pub const USAGE: &str = "\
+usage: rust_vm [command]
+
+commands:
+  asm <source> [-o <output>]  assemble a source file into a program file,
+                              written next to the source with an .ibc extension by default
+  run <file> [options]        run a program file or an assembly source file,
+                              exits with the status the program gives to hlt
+  disasm <file>               print a program file or source file as assembly source
+  repl                        start the interactive REPL, the default
+
+run options:
+  --max-heap <bytes>          fail allocations past this heap size
+  --stack-depth <slots>       fail calls and pushes past this stack depth
+  --trace                     print every instruction to stderr before running it";
+
+/// Exit codes of the driver itself, `run` otherwise exits with the program's status
+pub const EXIT_USAGE: i32 = 64;
+pub const EXIT_BAD_PROGRAM: i32 = 65;
+pub const EXIT_NO_INPUT: i32 = 66;
+pub const EXIT_VM_ERROR: i32 = 70;
+pub const EXIT_IO: i32 = 74;
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+  Asm { source: PathBuf, output: PathBuf },
+  Run { file: PathBuf, options: RunOptions },
+  Disasm { file: PathBuf },
+  Repl,
+  Help,
+}
+
+#[derive(Debug, PartialEq, Default)]
+pub struct RunOptions {
+  pub max_heap: Option<usize>,
+  pub stack_depth: Option<usize>,
+  pub trace: bool,
+}
+
+/// A failed command, with the exit code it ends the process with
+#[derive(Debug, PartialEq)]
+struct Failure {
+  code: i32,
+  message: String,
+}
+
+impl Failure {
+  fn new(code: i32, message: String) -> Failure {
+    Failure { code, message }
+  }
+}
+
+/// Runs the command given by `args`, without the program name, and returns the process exit code
+pub fn run(args: &[String]) -> i32 {
+  let command = match parse_args(args) {
+    Ok(command) => command,
+    Err(message) => {
+      eprintln!("error: {}\n\n{}", message, USAGE);
+      return EXIT_USAGE;
+    }
+  };
+  let result = match command {
+    Command::Asm { source, output } => assemble_file(&source, &output).map(|_| 0),
+    Command::Run { file, options } => run_file(&file, &options),
+    Command::Disasm { file } => load_file(&file).map(|bytecode| {
+      print!("{}", disassembler::disassemble(&bytecode));
+      0
+    }),
+    Command::Repl => REPL::new()
+      .run()
+      .map(|_| 0)
+      .map_err(|error| Failure::new(EXIT_IO, format!("REPL stopped: {}", error))),
+    Command::Help => {
+      println!("{}", USAGE);
+      Ok(0)
+    }
+  };
+  result.unwrap_or_else(|failure| {
+    eprintln!("{}", failure.message);
+    failure.code
+  })
+}
+
+pub fn parse_args(args: &[String]) -> Result<Command, String> {
+  let mut args = args.iter();
+  let command = match args.next() {
+    Some(command) => command.as_str(),
+    None => return Ok(Command::Repl)
+  };
+  let mut files = vec![];
+  let mut output = None;
+  let mut options = RunOptions::default();
+  while let Some(arg) = args.next() {
+    let mut value = |name: &str| args.next().cloned().ok_or(format!("{} needs a value", name));
+    match arg.as_str() {
+      "-o" if command == "asm" => output = Some(PathBuf::from(value(arg)?)),
+      "--max-heap" if command == "run" => options.max_heap = Some(parse_number(arg, &value(arg)?)?),
+      "--stack-depth" if command == "run" => options.stack_depth = Some(parse_number(arg, &value(arg)?)?),
+      "--trace" if command == "run" => options.trace = true,
+      flag if flag.starts_with('-') => return Err(format!("unknown option `{}` for `{}`", flag, command)),
+      file => files.push(PathBuf::from(file))
+    }
+  }
+  let file = || match files.len() {
+    1 => Ok(files[0].clone()),
+    0 => Err(format!("`{}` needs a file", command)),
+    _ => Err(format!("`{}` takes a single file", command))
+  };
+  match command {
+    "asm" => {
+      let source = file()?;
+      let output = output.unwrap_or_else(|| source.with_extension("ibc"));
+      Ok(Command::Asm { source, output })
+    }
+    "run" => Ok(Command::Run { file: file()?, options }),
+    "disasm" => Ok(Command::Disasm { file: file()? }),
+    "repl" if files.is_empty() => Ok(Command::Repl),
+    "repl" => Err("`repl` does not take a file".to_string()),
+    "help" | "-h" | "--help" => Ok(Command::Help),
+    other => Err(format!("unknown command `{}`", other))
+  }
+}
+
+fn parse_number(flag: &str, value: &str) -> Result<usize, String> {
+  value.parse().map_err(|_| format!("{} expects a number, got `{}`", flag, value))
+}
+
+fn read_file(path: &Path) -> Result<Vec<u8>, Failure> {
+  fs::read(path).map_err(|error| {
+    Failure::new(EXIT_NO_INPUT, format!("cannot read {}: {}", path.display(), error))
+  })
+}
+
+fn assemble_source(path: &Path, bytes: Vec<u8>) -> Result<Bytecode, Failure> {
+  let source = String::from_utf8(bytes).map_err(|_| {
+    Failure::new(EXIT_BAD_PROGRAM, format!("{} is neither a program file nor UTF-8 source", path.display()))
+  })?;
+  Assembler::new().assemble_bytecode(&source).map_err(|diagnostics| {
+    let mut message = String::new();
+    for diagnostic in &diagnostics {
+      message.push_str(&format!("{}\n", diagnostic));
+    }
+    message.push_str(&format!("{}: {} error(s)", path.display(), diagnostics.len()));
+    Failure::new(EXIT_BAD_PROGRAM, message)
+  })
+}
+
+/// Reads a program file, or assembles the file when it is source
+fn load_file(path: &Path) -> Result<Bytecode, Failure> {
+  let bytes = read_file(path)?;
+  if bytes.starts_with(&MAGIC) {
+    Bytecode::from_bytes(&bytes).map_err(|error| {
+      Failure::new(EXIT_BAD_PROGRAM, format!("cannot load {}: {}", path.display(), error))
+    })
+  } else {
+    assemble_source(path, bytes)
+  }
+}
+
+fn assemble_file(source: &Path, output: &Path) -> Result<(), Failure> {
+  let bytecode = assemble_source(source, read_file(source)?)?;
+  fs::write(output, bytecode.to_bytes()).map_err(|error| {
+    Failure::new(EXIT_IO, format!("cannot write {}: {}", output.display(), error))
+  })
+}
+
+fn run_file(path: &Path, options: &RunOptions) -> Result<i32, Failure> {
+  let bytecode = load_file(path)?;
+  let mut vm = VM::new();
+  if let Some(bytes) = options.max_heap {
+    vm = vm.with_max_heap_size(bytes);
+  }
+  if let Some(slots) = options.stack_depth {
+    vm = vm.with_stack_depth(slots);
+  }
+  vm.load_program(&bytecode.to_bytes()).map_err(|error| {
+    Failure::new(EXIT_BAD_PROGRAM, format!("cannot load {}: {}", path.display(), error))
+  })?;
+  let reason = if options.trace {
+    trace(&mut vm)
+  } else {
+    vm.run()
+  };
+  match reason {
+    Ok(reason) => Ok(reason.status().unwrap_or(0)),
+    Err(error) => Err(Failure::new(EXIT_VM_ERROR, describe_error(&vm, &error)))
+  }
+}
+
+/// Runs the program like `VM::run`, printing each instruction first
+fn trace(vm: &mut VM) -> Result<ExitReason, VmError> {
+  loop {
+    match disassembler::decode(&vm.program, vm.pc()) {
+      Some(instruction) => eprintln!("{:>6}: {}", vm.pc(), instruction),
+      None if vm.pc() < vm.program.len() => eprintln!("{:>6}: ; undecodable", vm.pc()),
+      None => {}
+    }
+    match vm.run_once()? {
+      ExitReason::Running => {},
+      reason => return Ok(reason)
+    }
+  }
+}
+
+fn describe_error(vm: &VM, error: &VmError) -> String {
+  let mut message = format!("error: {}\n  in {}", error, Location::new(error.pc, &vm.symbols));
+  if !error.backtrace.is_empty() {
+    message.push_str(&format!("\n{}", error.backtrace));
+  }
+  message
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::env;
+
+  fn args(line: &str) -> Vec<String> {
+    line.split_whitespace().map(String::from).collect()
+  }
+
+  #[test]
+  fn test_parse_args() {
+    assert_eq!(Ok(Command::Repl), parse_args(&[]));
+    assert_eq!(
+      Ok(Command::Asm { source: PathBuf::from("a.iasm"), output: PathBuf::from("a.ibc") }),
+      parse_args(&args("asm a.iasm"))
+    );
+    assert_eq!(
+      Ok(Command::Asm { source: PathBuf::from("a.iasm"), output: PathBuf::from("out") }),
+      parse_args(&args("asm -o out a.iasm"))
+    );
+    assert_eq!(
+      Ok(Command::Run {
+        file: PathBuf::from("a.ibc"),
+        options: RunOptions { max_heap: Some(64), stack_depth: None, trace: true }
+      }),
+      parse_args(&args("run a.ibc --trace --max-heap 64"))
+    );
+  }
+
+  #[test]
+  fn test_parse_args_errors() {
+    assert_eq!(Err("unknown command `build`".to_string()), parse_args(&args("build a")));
+    assert_eq!(Err("`run` needs a file".to_string()), parse_args(&args("run")));
+    assert_eq!(Err("--max-heap needs a value".to_string()), parse_args(&args("run a --max-heap")));
+    assert_eq!(
+      Err("--stack-depth expects a number, got `deep`".to_string()),
+      parse_args(&args("run a --stack-depth deep"))
+    );
+    assert_eq!(Err("unknown option `--trace` for `asm`".to_string()), parse_args(&args("asm a --trace")));
+  }
+
+  #[test]
+  fn test_assemble_and_run_file() {
+    let dir = env::temp_dir().join(format!("rust_vm_cli_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let source = dir.join("exit.iasm");
+    fs::write(&source, "load $0 #3\nhlt $0\n").unwrap();
+    let output = dir.join("exit.ibc");
+    assemble_file(&source, &output).unwrap();
+
+    assert_eq!(Ok(3), run_file(&output, &RunOptions::default()));
+    assert_eq!(Ok(3), run_file(&source, &RunOptions::default()));
+
+    fs::write(&source, "aloc $0 $1\n").unwrap();
+    let options = RunOptions { max_heap: Some(0), stack_depth: None, trace: false };
+    let failure = run_file(&source, &options).unwrap_err();
+    assert_eq!(EXIT_VM_ERROR, failure.code);
+
+    fs::write(&source, "lod $0 #1\n").unwrap();
+    assert_eq!(EXIT_BAD_PROGRAM, run_file(&source, &options).unwrap_err().code);
+    assert_eq!(EXIT_NO_INPUT, run_file(&dir.join("missing"), &options).unwrap_err().code);
+    fs::remove_dir_all(&dir).unwrap();
+  }
+}
@@ -0,0 +1,493 @@
+//! Turns bytecode back into assembly text, driven by the same opcode table as the assembler
+
+use alloc::collections::BTreeMap;
+use core::fmt;
+
+use bytecode::{Bytecode, DebugSymbol, SymbolSection};
+use instruction::{OpCode, OpCodeInfo, OperandKind, REGISTER_COUNT};
+use prelude::*;
+
+/// An operand as it is encoded in bytecode
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Operand {
+  Register(u8),
+  Integer(u16),
+  /// The signed offset of the load and store instructions
+  Offset(i16),
+}
+
+impl fmt::Display for Operand {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Operand::Register(register) => write!(f, "${}", register),
+      Operand::Integer(value) => write!(f, "#{}", value),
+      Operand::Offset(value) => write!(f, "#{}", value),
+    }
+  }
+}
+
+/// An instruction read back from bytecode
+#[derive(Debug, PartialEq, Clone)]
+pub struct DecodedInstruction {
+  /// Offset of the opcode byte in the code
+  pub offset: usize,
+  pub info: &'static OpCodeInfo,
+  pub operands: Vec<Operand>,
+}
+
+impl DecodedInstruction {
+  fn uses_register(&self, register: u8) -> bool {
+    self.operands.contains(&Operand::Register(register))
+  }
+}
+
+impl fmt::Display for DecodedInstruction {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.info.mnemonic)?;
+    for operand in &self.operands {
+      write!(f, " {}", operand)?;
+    }
+    Ok(())
+  }
+}
+
+/// Decodes the instruction at `offset`, `None` when the bytes there do not form one the
+/// assembler would produce
+pub fn decode(code: &[u8], offset: usize) -> Option<DecodedInstruction> {
+  let info = OpCode::from(*code.get(offset)?).info()?;
+  let bytes = code.get(offset..offset + info.size)?;
+  let signed_offset = info.operands.len() == 3 && info.operands[2] == OperandKind::Integer16;
+  let mut position = 1;
+  let mut operands = vec![];
+  for kind in info.operands {
+    match kind {
+      OperandKind::Register => {
+        if bytes[position] as usize >= REGISTER_COUNT {
+          return None;
+        }
+        operands.push(Operand::Register(bytes[position]));
+      }
+      OperandKind::Integer16 => {
+        let value = (u16::from(bytes[position]) << 8) | u16::from(bytes[position + 1]);
+        operands.push(if signed_offset { Operand::Offset(value as i16) } else { Operand::Integer(value) });
+      }
+    }
+    position += kind.width();
+  }
+  // the VM skips the padding but the assembler always writes zeros
+  if bytes[position..].iter().any(|&byte| byte != 0) {
+    return None;
+  }
+  Some(DecodedInstruction { offset, info, operands })
+}
+
+enum Line {
+  Instruction(DecodedInstruction),
+  Undecodable { offset: usize, byte: u8 },
+}
+
+/// Assembly source for `bytecode` that assembles back to the same code and data sections
+///
+/// Labels come from the program's debug symbols, or are synthesized as `L<offset>` for CALL
+/// targets and for LOAD constants a jump uses as its target. LOAD constants used as the address
+/// of a load, store or `prts` are written as the data symbol at that offset when there is one.
+/// Bytes that do not decode are listed as comments, so the text only assembles back to the same
+/// code when every byte decodes.
+pub fn disassemble(bytecode: &Bytecode) -> String {
+  let lines = decode_all(&bytecode.code);
+  let mut boundaries: Vec<usize> = lines
+    .iter()
+    .filter_map(|line| match line {
+      Line::Instruction(instruction) => Some(instruction.offset),
+      Line::Undecodable { .. } => None
+    })
+    .collect();
+  boundaries.push(bytecode.code.len());
+
+  let mut code_labels = symbol_labels(&bytecode.symbols, SymbolSection::Code, |offset| boundaries.contains(&offset));
+  let data_labels = symbol_labels(&bytecode.symbols, SymbolSection::Data, |offset| offset <= bytecode.data.len());
+  let mut targets = BTreeMap::new();
+  for index in 0..lines.len() {
+    match loaded_address(&lines, index) {
+      Some((SymbolSection::Code, target)) if boundaries.contains(&target) => {
+        targets.insert(index, (SymbolSection::Code, target));
+        if !code_labels.contains_key(&target) {
+          let name = unused_name(target, &code_labels, &data_labels);
+          code_labels.insert(target, vec![name]);
+        }
+      }
+      Some((SymbolSection::Data, offset)) if data_labels.contains_key(&offset) => {
+        targets.insert(index, (SymbolSection::Data, offset));
+      }
+      _ => {}
+    }
+  }
+
+  let mut text = String::new();
+  for (index, line) in lines.iter().enumerate() {
+    match line {
+      Line::Instruction(instruction) => {
+        push_labels(&mut text, &code_labels, instruction.offset);
+        let mut source = instruction.info.mnemonic.to_string();
+        for operand in &instruction.operands {
+          match (operand, targets.get(&index)) {
+            (Operand::Integer(_), Some((section, address))) => {
+              let labels = if *section == SymbolSection::Code { &code_labels } else { &data_labels };
+              source.push_str(&format!(" @{}", labels[address][0]))
+            }
+            _ => source.push_str(&format!(" {}", operand)),
+          }
+        }
+        text.push_str(&format!("  {:<24}; {}\n", source, instruction.offset));
+      }
+      Line::Undecodable { offset, byte } => {
+        push_labels(&mut text, &code_labels, *offset);
+        text.push_str(&format!("  ; undecodable byte {:#04x} at {}\n", byte, offset));
+      }
+    }
+  }
+  push_labels(&mut text, &code_labels, bytecode.code.len());
+
+  if !bytecode.data.is_empty() || !data_labels.is_empty() {
+    text.push_str(".data\n");
+    let mut starts: Vec<usize> = data_labels.keys().cloned().collect();
+    starts.push(bytecode.data.len());
+    let mut offset = 0;
+    for end in starts {
+      data_directives(&mut text, &bytecode.data[offset..end]);
+      offset = end;
+      push_labels(&mut text, &data_labels, offset);
+    }
+  }
+  text
+}
+
+/// One line per instruction, prefixed with its offset, bytes that do not decode are listed on their own
+pub fn listing(code: &[u8]) -> String {
+  let mut text = String::new();
+  for line in decode_all(code) {
+    match line {
+      Line::Instruction(instruction) => text.push_str(&format!("{:>6}: {}\n", instruction.offset, instruction)),
+      Line::Undecodable { offset, byte } => {
+        text.push_str(&format!("{:>6}: ; undecodable byte {:#04x}\n", offset, byte))
+      }
+    }
+  }
+  text
+}
+
+/// Decodes the code from the start, skipping a single byte where decoding fails
+fn decode_all(code: &[u8]) -> Vec<Line> {
+  let mut lines = vec![];
+  let mut offset = 0;
+  while offset < code.len() {
+    match decode(code, offset) {
+      Some(instruction) => {
+        offset += instruction.info.size;
+        lines.push(Line::Instruction(instruction));
+      }
+      None => {
+        lines.push(Line::Undecodable { offset, byte: code[offset] });
+        offset += 1;
+      }
+    }
+  }
+  lines
+}
+
+/// The address the integer operand of `lines[index]` holds, if it can be told: the target of a
+/// CALL, or a LOAD constant that a jump, or a load, store or `prts`, uses as its address before
+/// its register is touched again
+fn loaded_address(lines: &[Line], index: usize) -> Option<(SymbolSection, usize)> {
+  let instruction = match &lines[index] {
+    Line::Instruction(instruction) => instruction,
+    Line::Undecodable { .. } => return None
+  };
+  let (register, value) = match (instruction.info.opcode, instruction.operands.as_slice()) {
+    (OpCode::CALL, [Operand::Integer(value)]) => return Some((SymbolSection::Code, *value as usize)),
+    (OpCode::LOAD, [Operand::Register(register), Operand::Integer(value)]) => (*register, *value),
+    _ => return None
+  };
+  for line in &lines[index + 1..] {
+    let next = match line {
+      Line::Instruction(next) => next,
+      Line::Undecodable { .. } => return None
+    };
+    if next.uses_register(register) {
+      let address = Operand::Register(register);
+      return match next.info.opcode {
+        opcode if is_absolute_jump(opcode) && next.operands[0] == address => {
+          Some((SymbolSection::Code, value as usize))
+        }
+        opcode if is_memory_access(opcode) && next.operands[1] == address => {
+          Some((SymbolSection::Data, value as usize))
+        }
+        OpCode::PRTS => Some((SymbolSection::Data, value as usize)),
+        _ => None
+      };
+    }
+    // control leaves the straight line, or a host function may change any register
+    match next.info.opcode {
+      OpCode::JMP | OpCode::JMPF | OpCode::JMPB | OpCode::CALL | OpCode::RET |
+      OpCode::HLT | OpCode::EXIT | OpCode::SYSCALL => return None,
+      _ => {}
+    }
+  }
+  None
+}
+
+/// Jumps that take the target address from their register, JMPF and JMPB jump by a distance
+fn is_absolute_jump(opcode: OpCode) -> bool {
+  matches!(
+    opcode,
+    OpCode::JMP | OpCode::JEQ | OpCode::JNEQ | OpCode::JC | OpCode::JO | OpCode::JZ |
+    OpCode::JNZ | OpCode::JS | OpCode::JNS | OpCode::JNC | OpCode::JNO | OpCode::JLT |
+    OpCode::JGE | OpCode::JGT | OpCode::JLE
+  )
+}
+
+/// Loads and stores, which address memory with their second register plus an offset
+fn is_memory_access(opcode: OpCode) -> bool {
+  matches!(
+    opcode,
+    OpCode::LB | OpCode::LBU | OpCode::LH | OpCode::LHU | OpCode::LW | OpCode::SB | OpCode::SH | OpCode::SW
+  )
+}
+
+/// Names of the symbols in `section` by offset, leaving out the ones the assembler could not
+/// declare again
+fn symbol_labels<F>(symbols: &[DebugSymbol], section: SymbolSection, valid: F) -> BTreeMap<usize, Vec<String>>
+  where F: Fn(usize) -> bool
+{
+  let mut labels: BTreeMap<usize, Vec<String>> = BTreeMap::new();
+  for symbol in symbols {
+    let offset = symbol.offset as usize;
+    let name = &symbol.name;
+    let declarable = !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric());
+    if symbol.section == section && declarable && valid(offset) {
+      labels.entry(offset).or_default().push(name.clone());
+    }
+  }
+  labels
+}
+
+fn unused_name(offset: usize, code_labels: &BTreeMap<usize, Vec<String>>, data_labels: &BTreeMap<usize, Vec<String>>) -> String {
+  let taken = |name: &str| code_labels.values().chain(data_labels.values()).flatten().any(|label| label == name);
+  let mut name = format!("L{}", offset);
+  while taken(&name) {
+    name.insert(0, 'L');
+  }
+  name
+}
+
+fn push_labels(text: &mut String, labels: &BTreeMap<usize, Vec<String>>, offset: usize) {
+  for name in labels.get(&offset).into_iter().flatten() {
+    text.push_str(&format!("{}:\n", name));
+  }
+}
+
+/// Directives reproducing `data`: NUL terminated text as `.asciiz`, runs of zeros as `.space`
+/// and anything else byte by byte
+fn data_directives(text: &mut String, data: &[u8]) {
+  let mut offset = 0;
+  while offset < data.len() {
+    let printable = data[offset..].iter().take_while(|&&byte| is_printable(byte)).count();
+    let zeros = data[offset..].iter().take_while(|&&byte| byte == 0).take(usize::from(u16::MAX)).count();
+    if printable > 0 && data.get(offset + printable) == Some(&0) {
+      let string: String = data[offset..offset + printable].iter().map(|&byte| escape(byte)).collect();
+      text.push_str(&format!("  .asciiz \"{}\"\n", string));
+      offset += printable + 1;
+    } else if zeros > 1 {
+      text.push_str(&format!("  .space {}\n", zeros));
+      offset += zeros;
+    } else {
+      text.push_str(&format!("  .byte {}\n", data[offset]));
+      offset += 1;
+    }
+  }
+}
+
+fn is_printable(byte: u8) -> bool {
+  (b' '..=b'~').contains(&byte) || byte == b'\n' || byte == b'\t' || byte == b'\r'
+}
+
+fn escape(byte: u8) -> String {
+  match byte {
+    b'\n' => "\\n".to_string(),
+    b'\t' => "\\t".to_string(),
+    b'\r' => "\\r".to_string(),
+    b'"' => "\\\"".to_string(),
+    b'\\' => "\\\\".to_string(),
+    byte => (byte as char).to_string(),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_decode() {
+    let code = [OpCode::LOAD as u8, 2, 1, 244, OpCode::HLT as u8];
+    let instruction = decode(&code, 0).unwrap();
+    assert_eq!(vec![Operand::Register(2), Operand::Integer(500)], instruction.operands);
+    assert_eq!("load $2 #500", instruction.to_string());
+    assert_eq!("hlt", decode(&code, 4).unwrap().to_string());
+    assert_eq!("lw $1 $2 #-4", decode(&[OpCode::LW as u8, 1, 2, 255, 252], 0).unwrap().to_string());
+    // truncated, unknown opcode, register out of range and padding that is not zero
+    assert_eq!(None, decode(&code[..3], 0));
+    assert_eq!(None, decode(&[255], 0));
+    assert_eq!(None, decode(&[OpCode::INC as u8, 32], 0));
+    assert_eq!(None, decode(&[OpCode::EQ as u8, 0, 1, 7], 0));
+  }
+
+  #[test]
+  fn test_listing() {
+    let code = [OpCode::INC as u8, 1, 255, OpCode::HLT as u8];
+    assert_eq!("     0: inc $1\n     2: ; undecodable byte 0xff\n     3: hlt\n", listing(&code));
+  }
+
+  #[test]
+  fn test_disassemble_undecodable() {
+    let bytecode = Bytecode::new(vec![OpCode::INC as u8, 1, 255, OpCode::HLT as u8], vec![]);
+    assert_eq!(
+      "  inc $1                  ; 0\n  ; undecodable byte 0xff at 2\n  hlt                     ; 3\n",
+      disassemble(&bytecode)
+    );
+  }
+}
+
+/// Round trips through the assembler
+#[cfg(all(test, feature = "assembler"))]
+mod assembler_tests {
+  use super::*;
+  use assembler::Assembler;
+  use assembler::Token;
+  use assembler::instruction_parsers::AssemblerInstruction;
+  use assembler::program_parsers::Program;
+  use assembler::symbols::SymbolTable;
+  use instruction::OPCODES;
+
+  #[test]
+  fn test_disassemble() {
+    let mut bytecode = Assembler::new().assemble_bytecode(
+      "load $0 @loop\nload $1 #3\nloop: dec $1\ncmp $1 $2\njnz $0\ncall @done\nprts $1\ndone: hlt\n\
+       .data\nmsg: .asciiz \"hi \\\"you\\\"\\n\"\n.byte 7\n.space 3\n"
+    ).unwrap();
+    // symbols are optional, targets the disassembler can infer get labels of their own
+    bytecode.symbols.retain(|symbol| symbol.name != "done");
+    let text = disassemble(&bytecode);
+    assert_eq!(
+      "  load $0 @loop           ; 0\n\
+       \x20 load $1 #3              ; 4\n\
+       loop:\n\
+       \x20 dec $1                  ; 8\n\
+       \x20 cmp $1 $2               ; 10\n\
+       \x20 jnz $0                  ; 14\n\
+       \x20 call @L21               ; 16\n\
+       \x20 prts $1                 ; 19\n\
+       L21:\n\
+       \x20 hlt                     ; 21\n\
+       .data\n\
+       msg:\n\
+       \x20 .asciiz \"hi \\\"you\\\"\\n\"\n\
+       \x20 .byte 7\n\
+       \x20 .space 3\n",
+      text
+    );
+    let reassembled = Assembler::new().assemble_bytecode(&text).unwrap();
+    assert_eq!(bytecode.code, reassembled.code);
+    assert_eq!(bytecode.data, reassembled.data);
+  }
+
+  #[test]
+  fn test_program_file_round_trip() {
+    let bytes = Assembler::new().assemble(
+      ".data\ngreeting: .asciiz \"hi\"\ntable: .byte 1\n.byte 2\n\
+       .code\nstart: load $0 @greeting\nprts $0\nload $1 @table\nlb $2 $1 #1\ncall @done\ndone: hlt $2\n"
+    ).unwrap();
+    let text = disassemble(&Bytecode::from_bytes(&bytes).unwrap());
+    assert!(text.contains("load $0 @greeting"), "{}", text);
+    assert!(text.contains("load $1 @table"), "{}", text);
+    assert_eq!(bytes, Assembler::new().assemble(&text).unwrap(), "{}", text);
+  }
+
+  /// xorshift, enough to vary the generated programs without a dependency
+  struct Rng(u64);
+
+  impl Rng {
+    fn next(&mut self) -> u64 {
+      self.0 ^= self.0 << 13;
+      self.0 ^= self.0 >> 7;
+      self.0 ^= self.0 << 17;
+      self.0
+    }
+
+    fn below(&mut self, bound: u64) -> i64 {
+      (self.next() % bound) as i64
+    }
+  }
+
+  fn random_instruction(rng: &mut Rng) -> AssemblerInstruction {
+    let info = &OPCODES[rng.below(OPCODES.len() as u64) as usize];
+    let mut operands = info.operands.iter().map(|kind| match kind {
+      OperandKind::Register => Token::Register { reg_num: rng.below(REGISTER_COUNT as u64) as u8 },
+      OperandKind::Integer16 => Token::IntegerOperand { value: rng.below(1 << 16) },
+    });
+    AssemblerInstruction {
+      opcode: Some(Token::Op { code: info.opcode }),
+      label: None,
+      directive: None,
+      operand1: operands.next(),
+      operand2: operands.next(),
+      operand3: operands.next(),
+    }
+  }
+
+  fn random_directive(rng: &mut Rng) -> AssemblerInstruction {
+    let (name, operand) = match rng.below(4) {
+      0 => ("byte", Token::IntegerOperand { value: rng.below(256) }),
+      1 => ("integer", Token::IntegerOperand { value: rng.below(1 << 32) }),
+      2 => ("space", Token::IntegerOperand { value: rng.below(5) }),
+      _ => {
+        let length = rng.below(6);
+        let value = (0..length).map(|_| (rng.below(96) as u8 + 31) as char).collect();
+        ("asciiz", Token::StringOperand { value })
+      }
+    };
+    AssemblerInstruction {
+      opcode: None,
+      label: None,
+      directive: Some(Token::Directive { name: name.to_string() }),
+      operand1: Some(operand),
+      operand2: None,
+      operand3: None,
+    }
+  }
+
+  #[test]
+  fn test_round_trip_random_programs() {
+    let mut rng = Rng(0x2545_F491_4F6C_DD1D);
+    for _ in 0..200 {
+      let mut instructions: Vec<_> = (0..rng.below(30)).map(|_| random_instruction(&mut rng)).collect();
+      instructions.push(AssemblerInstruction {
+        opcode: None,
+        label: None,
+        directive: Some(Token::Directive { name: "data".to_string() }),
+        operand1: None,
+        operand2: None,
+        operand3: None,
+      });
+      instructions.extend((0..rng.below(10)).map(|_| random_directive(&mut rng)));
+      let bytes = Program { instructions }.to_bytes(&SymbolTable::new()).unwrap();
+      let bytecode = Bytecode::from_bytes(&bytes).unwrap();
+
+      let text = disassemble(&bytecode);
+      let reassembled = Assembler::new().assemble_bytecode(&text).unwrap_or_else(|diagnostics| {
+        panic!("{}\ndoes not assemble: {:?}", text, diagnostics)
+      });
+      assert_eq!(bytecode.code, reassembled.code, "{}", text);
+      assert_eq!(bytecode.data, reassembled.data, "{}", text);
+    }
+  }
+}
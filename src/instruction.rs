@@ -1,5 +1,8 @@
+#[cfg(feature = "assembler")]
 use nom::types::CompleteStr;
 
+use vm::VmErrorKind;
+
 #[derive(Debug,PartialEq, Clone, Copy)]
 pub enum OpCode {
   LOAD, // 0
@@ -22,74 +25,265 @@ pub enum OpCode {
   ALOC, // 17
   INC, // 18
   DEC, // 19
+  JC, // 20
+  JO, // 21
+  REM, // 22
+  CMP, // 23
+  JZ, // 24
+  JNZ, // 25
+  JS, // 26
+  JNS, // 27
+  JNC, // 28
+  JNO, // 29
+  JLT, // 30
+  JGE, // 31
+  JGT, // 32
+  JLE, // 33
+  AND, // 34
+  OR, // 35
+  XOR, // 36
+  NOT, // 37
+  SHL, // 38
+  SHR, // 39
+  SAR, // 40
+  ROL, // 41
+  ROR, // 42
+  LUI, // 43
+  LB, // 44
+  LBU, // 45
+  LH, // 46
+  LHU, // 47
+  LW, // 48
+  SB, // 49
+  SH, // 50
+  SW, // 51
+  FREE, // 52
+  CALL, // 53
+  RET, // 54
+  PUSH, // 55
+  POP, // 56
+  SYSCALL, // 57
+  PRTI, // 58
+  PRTC, // 59
+  PRTS, // 60
+  READI, // 61
+  READS, // 62
+  EXIT, // 63, `hlt $r` in assembly
   IGL // unknown codes
 }
 
 impl From<u8> for OpCode {
   fn from(code : u8) -> Self {
-    match code {
-      0 => OpCode::LOAD,
-      1 => OpCode::ADD,
-      2 => OpCode::SUB,
-      3 => OpCode::MUL,
-      4 => OpCode::DIV,
-      5 => OpCode::HLT,
-      6 => OpCode::JMP,
-      7 => OpCode::JMPF,
-      8 => OpCode::JMPB,
-      9 => OpCode::EQ,
-      10 => OpCode::NEQ,
-      11 => OpCode::GT,
-      12 => OpCode::LT,
-      13 => OpCode::GTE,
-      14 => OpCode::LTE,
-      15 => OpCode::JEQ,
-      16 => OpCode::JNEQ,
-      17 => OpCode::ALOC,
-      18 => OpCode::INC,
-      19 => OpCode::DEC,
-      _ => OpCode::IGL
-    }
+    OPCODES.get(code as usize).map_or(OpCode::IGL, |info| info.opcode)
   }
 }
 
+impl<'a> From<&'a str> for OpCode {
+  fn from(mnemonic: &'a str) -> OpCode {
+    OPCODES
+      .iter()
+      .find(|info| info.mnemonic == mnemonic)
+      .map_or(OpCode::IGL, |info| info.opcode)
+  }
+}
+
+#[cfg(feature = "assembler")]
 impl<'a> From<CompleteStr<'a>> for OpCode {
   fn from(v: CompleteStr<'a>) -> OpCode {
-    match v {
-      CompleteStr("load") => OpCode::LOAD,
-      CompleteStr("add") => OpCode::ADD,
-      CompleteStr("sub") => OpCode::SUB,
-      CompleteStr("mul") => OpCode::MUL,
-      CompleteStr("div") => OpCode::DIV,
-      CompleteStr("hlt") => OpCode::HLT,
-      CompleteStr("jmp") => OpCode::JMP,
-      CompleteStr("jmpf") => OpCode::JMPF,
-      CompleteStr("jmpb") => OpCode::JMPB,
-      CompleteStr("eq") => OpCode::EQ,
-      CompleteStr("neq") => OpCode::NEQ,
-      CompleteStr("gt") => OpCode::GT,
-      CompleteStr("lt") => OpCode::LT,
-      CompleteStr("gte") => OpCode::GTE,
-      CompleteStr("lte") => OpCode::LTE,
-      CompleteStr("jeq") => OpCode::JEQ,
-      CompleteStr("jneq") => OpCode::JNEQ,
-      CompleteStr("aloc") => OpCode::ALOC,
-      CompleteStr("inc") => OpCode::INC,
-      CompleteStr("dec") => OpCode::DEC,
-      _ => OpCode::IGL,
+    OpCode::from(v.0)
+  }
+}
+
+impl OpCode {
+  /// Metadata for the opcode, `None` for IGL
+  pub fn info(self) -> Option<&'static OpCodeInfo> {
+    OPCODES.get(self as usize)
+  }
+
+  /// Name of the opcode in assembly source
+  pub fn mnemonic(self) -> &'static str {
+    self.info().map_or("igl", |info| info.mnemonic)
+  }
+
+  /// Number of operands the opcode takes in assembly source
+  pub fn operand_count(self) -> usize {
+    self.info().map_or(0, |info| info.operands.len())
+  }
+}
+
+/// Revision of the instruction set recorded in program files, bump it when opcodes are added
+/// or change meaning so older VMs refuse programs they cannot run
+pub const INSTRUCTION_SET_REVISION: u16 = 7;
+
+/// Oldest revision this VM still runs, revision 3 changed the operands of `aloc`
+pub const MIN_INSTRUCTION_SET_REVISION: u16 = 3;
+
+/// Number of general purpose registers in the VM
+pub const REGISTER_COUNT: usize = 32;
+
+/// Kind of an encoded operand
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum OperandKind {
+  /// A register number, one byte
+  Register,
+  /// An integer or a label address, two bytes big endian
+  Integer16,
+}
+
+impl OperandKind {
+  /// Number of bytes the operand takes in bytecode
+  pub fn width(self) -> usize {
+    match self {
+      OperandKind::Register => 1,
+      OperandKind::Integer16 => 2,
     }
   }
 }
 
+/// Everything the VM, assembler and disassembler need to know about an opcode
+#[derive(Debug, PartialEq)]
+pub struct OpCodeInfo {
+  pub opcode: OpCode,
+  pub mnemonic: &'static str,
+  pub operands: &'static [OperandKind],
+  /// Encoded size in bytes, opcode included, operands are followed by zero padding up to it
+  pub size: usize,
+}
+
+impl OpCodeInfo {
+  const fn new(opcode: OpCode, mnemonic: &'static str, operands: &'static [OperandKind], size: usize) -> OpCodeInfo {
+    OpCodeInfo { opcode, mnemonic, operands, size }
+  }
+}
+
+const NONE: &[OperandKind] = &[];
+const REG: &[OperandKind] = &[OperandKind::Register];
+const INT: &[OperandKind] = &[OperandKind::Integer16];
+const REG_REG: &[OperandKind] = &[OperandKind::Register, OperandKind::Register];
+const REG_REG_REG: &[OperandKind] = &[OperandKind::Register, OperandKind::Register, OperandKind::Register];
+const REG_INT: &[OperandKind] = &[OperandKind::Register, OperandKind::Integer16];
+const REG_REG_INT: &[OperandKind] = &[OperandKind::Register, OperandKind::Register, OperandKind::Integer16];
+
+/// Metadata of every opcode, indexed by opcode byte
+pub const OPCODES: &[OpCodeInfo] = &[
+  OpCodeInfo::new(OpCode::LOAD, "load", REG_INT, 4),
+  OpCodeInfo::new(OpCode::ADD, "add", REG_REG_REG, 4),
+  OpCodeInfo::new(OpCode::SUB, "sub", REG_REG_REG, 4),
+  OpCodeInfo::new(OpCode::MUL, "mul", REG_REG_REG, 4),
+  OpCodeInfo::new(OpCode::DIV, "div", REG_REG_REG, 4),
+  OpCodeInfo::new(OpCode::HLT, "hlt", NONE, 1),
+  OpCodeInfo::new(OpCode::JMP, "jmp", REG, 2),
+  OpCodeInfo::new(OpCode::JMPF, "jmpf", REG, 2),
+  OpCodeInfo::new(OpCode::JMPB, "jmpb", REG, 2),
+  OpCodeInfo::new(OpCode::EQ, "eq", REG_REG, 4),
+  OpCodeInfo::new(OpCode::NEQ, "neq", REG_REG, 4),
+  OpCodeInfo::new(OpCode::GT, "gt", REG_REG, 4),
+  OpCodeInfo::new(OpCode::LT, "lt", REG_REG, 4),
+  OpCodeInfo::new(OpCode::GTE, "gte", REG_REG, 4),
+  OpCodeInfo::new(OpCode::LTE, "lte", REG_REG, 4),
+  OpCodeInfo::new(OpCode::JEQ, "jeq", REG, 2),
+  OpCodeInfo::new(OpCode::JNEQ, "jneq", REG, 2),
+  OpCodeInfo::new(OpCode::ALOC, "aloc", REG_REG, 3),
+  OpCodeInfo::new(OpCode::INC, "inc", REG, 2),
+  OpCodeInfo::new(OpCode::DEC, "dec", REG, 2),
+  OpCodeInfo::new(OpCode::JC, "jc", REG, 2),
+  OpCodeInfo::new(OpCode::JO, "jo", REG, 2),
+  OpCodeInfo::new(OpCode::REM, "rem", REG, 2),
+  OpCodeInfo::new(OpCode::CMP, "cmp", REG_REG, 4),
+  OpCodeInfo::new(OpCode::JZ, "jz", REG, 2),
+  OpCodeInfo::new(OpCode::JNZ, "jnz", REG, 2),
+  OpCodeInfo::new(OpCode::JS, "js", REG, 2),
+  OpCodeInfo::new(OpCode::JNS, "jns", REG, 2),
+  OpCodeInfo::new(OpCode::JNC, "jnc", REG, 2),
+  OpCodeInfo::new(OpCode::JNO, "jno", REG, 2),
+  OpCodeInfo::new(OpCode::JLT, "jlt", REG, 2),
+  OpCodeInfo::new(OpCode::JGE, "jge", REG, 2),
+  OpCodeInfo::new(OpCode::JGT, "jgt", REG, 2),
+  OpCodeInfo::new(OpCode::JLE, "jle", REG, 2),
+  OpCodeInfo::new(OpCode::AND, "and", REG_REG_REG, 4),
+  OpCodeInfo::new(OpCode::OR, "or", REG_REG_REG, 4),
+  OpCodeInfo::new(OpCode::XOR, "xor", REG_REG_REG, 4),
+  OpCodeInfo::new(OpCode::NOT, "not", REG_REG, 3),
+  OpCodeInfo::new(OpCode::SHL, "shl", REG_REG_REG, 4),
+  OpCodeInfo::new(OpCode::SHR, "shr", REG_REG_REG, 4),
+  OpCodeInfo::new(OpCode::SAR, "sar", REG_REG_REG, 4),
+  OpCodeInfo::new(OpCode::ROL, "rol", REG_REG_REG, 4),
+  OpCodeInfo::new(OpCode::ROR, "ror", REG_REG_REG, 4),
+  OpCodeInfo::new(OpCode::LUI, "lui", REG_INT, 4),
+  OpCodeInfo::new(OpCode::LB, "lb", REG_REG_INT, 5),
+  OpCodeInfo::new(OpCode::LBU, "lbu", REG_REG_INT, 5),
+  OpCodeInfo::new(OpCode::LH, "lh", REG_REG_INT, 5),
+  OpCodeInfo::new(OpCode::LHU, "lhu", REG_REG_INT, 5),
+  OpCodeInfo::new(OpCode::LW, "lw", REG_REG_INT, 5),
+  OpCodeInfo::new(OpCode::SB, "sb", REG_REG_INT, 5),
+  OpCodeInfo::new(OpCode::SH, "sh", REG_REG_INT, 5),
+  OpCodeInfo::new(OpCode::SW, "sw", REG_REG_INT, 5),
+  OpCodeInfo::new(OpCode::FREE, "free", REG, 2),
+  OpCodeInfo::new(OpCode::CALL, "call", INT, 3),
+  OpCodeInfo::new(OpCode::RET, "ret", NONE, 1),
+  OpCodeInfo::new(OpCode::PUSH, "push", REG, 2),
+  OpCodeInfo::new(OpCode::POP, "pop", REG, 2),
+  OpCodeInfo::new(OpCode::SYSCALL, "syscall", INT, 3),
+  OpCodeInfo::new(OpCode::PRTI, "prti", REG, 2),
+  OpCodeInfo::new(OpCode::PRTC, "prtc", REG, 2),
+  OpCodeInfo::new(OpCode::PRTS, "prts", REG, 2),
+  OpCodeInfo::new(OpCode::READI, "readi", REG, 2),
+  OpCodeInfo::new(OpCode::READS, "reads", REG, 2),
+  OpCodeInfo::new(OpCode::EXIT, "exit", REG, 2),
+];
+
+/// An instruction with its operands read out of the bytecode, what the VM dispatches on
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub struct Instruction {
-  opcode: OpCode
+  pub opcode: OpCode,
+  /// Register numbers and integers in the order the opcode table lists them
+  pub operands: [u16; 3],
+  /// Encoded size in bytes, the next instruction starts this far after this one
+  pub size: u8,
 }
 
 impl Instruction {
   pub fn new(opcode: OpCode) -> Instruction {
     Instruction {
-      opcode
+      opcode,
+      operands: [0; 3],
+      size: opcode.info().map_or(1, |info| info.size as u8)
+    }
+  }
+
+  /// Decodes the instruction at `offset`, failing the way executing it would: on an unknown
+  /// opcode, a register outside of the register file or the code ending before the instruction
+  pub fn decode(code: &[u8], offset: usize) -> Result<Instruction, VmErrorKind> {
+    let code_byte = *code.get(offset).ok_or(VmErrorKind::TruncatedInstruction)?;
+    let info = OpCode::from(code_byte).info().ok_or(VmErrorKind::IllegalOpcode)?;
+    let mut instruction = Instruction::new(info.opcode);
+    let mut position = offset + 1;
+    for (index, kind) in info.operands.iter().enumerate() {
+      let bytes = code.get(position..position + kind.width()).ok_or(VmErrorKind::TruncatedInstruction)?;
+      instruction.operands[index] = match kind {
+        OperandKind::Register if bytes[0] as usize >= REGISTER_COUNT => {
+          return Err(VmErrorKind::RegisterOutOfRange { register: bytes[0] });
+        }
+        OperandKind::Register => u16::from(bytes[0]),
+        OperandKind::Integer16 => (u16::from(bytes[0]) << 8) | u16::from(bytes[1]),
+      };
+      position += kind.width();
     }
+    // padding counts too
+    if offset + info.size > code.len() {
+      return Err(VmErrorKind::TruncatedInstruction);
+    }
+    Ok(instruction)
+  }
+
+  /// The register operand at `index`
+  pub fn register(&self, index: usize) -> usize {
+    self.operands[index] as usize
+  }
+
+  /// The integer operand at `index`
+  pub fn integer(&self, index: usize) -> u16 {
+    self.operands[index]
   }
 }
 
@@ -110,8 +304,62 @@ mod tests {
   }
 
   #[test]
+  fn test_decode() {
+    let code = [OpCode::LOAD as u8, 3, 1, 244, OpCode::EQ as u8, 1, 2, 0];
+    let load = Instruction::decode(&code, 0).unwrap();
+    assert_eq!(Instruction { opcode: OpCode::LOAD, operands: [3, 500, 0], size: 4 }, load);
+    assert_eq!(3, load.register(0));
+    assert_eq!(500, load.integer(1));
+    assert_eq!([1, 2, 0], Instruction::decode(&code, 4).unwrap().operands);
+  }
+
+  #[test]
+  fn test_decode_errors() {
+    assert_eq!(Err(VmErrorKind::IllegalOpcode), Instruction::decode(&[200], 0));
+    assert_eq!(
+      Err(VmErrorKind::RegisterOutOfRange { register: 40 }),
+      Instruction::decode(&[OpCode::ADD as u8, 1, 40], 0)
+    );
+    assert_eq!(Err(VmErrorKind::TruncatedInstruction), Instruction::decode(&[OpCode::LOAD as u8, 1, 0], 0));
+    // the padding byte of a comparison is missing
+    assert_eq!(Err(VmErrorKind::TruncatedInstruction), Instruction::decode(&[OpCode::EQ as u8, 1, 2], 0));
+  }
+
+  #[test]
+  #[cfg(feature = "assembler")]
   fn fn_test_from() {
       let result = OpCode::from(CompleteStr("load"));
       assert_eq!(OpCode::LOAD, result);
   }
+
+  #[test]
+  fn test_flag_jump_mnemonics() {
+    assert_eq!(OpCode::JLT, OpCode::from("jlt"));
+    assert_eq!(OpCode::JGE, OpCode::from("jge"));
+    assert_eq!(OpCode::JZ, OpCode::from(24));
+  }
+
+  #[test]
+  fn test_opcode_table() {
+    for (code, info) in OPCODES.iter().enumerate() {
+      assert_eq!(code, info.opcode as usize);
+      assert_eq!(info.opcode, OpCode::from(code as u8));
+      let operands: usize = info.operands.iter().map(|kind| kind.width()).sum();
+      // the opcode byte comes before the operands
+      assert!(operands < info.size, "{:?} operands overflow its size", info.opcode);
+    }
+    assert_eq!(OPCODES.len(), OpCode::IGL as usize);
+    assert_eq!(OpCode::IGL, OpCode::from(OPCODES.len() as u8));
+    assert_eq!(None, OpCode::IGL.info());
+  }
+
+  #[test]
+  fn test_mnemonic_round_trip() {
+    for code in 0..=u8::MAX {
+      let opcode = OpCode::from(code);
+      if opcode != OpCode::IGL {
+        assert_eq!(opcode, OpCode::from(opcode.mnemonic()));
+      }
+    }
+  }
 }
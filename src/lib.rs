@@ -0,0 +1,41 @@
+//! A register based virtual machine, its assembler and disassembler
+//!
+//! The `assembler` feature brings in the nom based assembler, embedders that only run program
+//! files can turn default features off to leave it and the REPL binary out. Without the `std`
+//! feature the VM core builds as `no_std` with `alloc`, and has no console for the I/O opcodes.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+#![warn(clippy::all)]
+#[cfg_attr(not(feature = "std"), macro_use)]
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate core;
+#[cfg(feature = "assembler")]
+#[macro_use]
+extern crate nom;
+
+/// The parts of the std prelude the VM core uses, taken from `alloc` so they exist without std
+mod prelude {
+  pub use alloc::boxed::Box;
+  pub use alloc::string::{String, ToString};
+  pub use alloc::vec::Vec;
+}
+
+pub mod vm;
+pub mod instruction;
+#[cfg(feature = "assembler")]
+pub mod assembler;
+pub mod bytecode;
+pub mod disassembler;
+
+#[cfg(feature = "assembler")]
+pub use assembler::Assembler;
+#[cfg(feature = "assembler")]
+pub use assembler::assembler_errors::AssemblerError;
+#[cfg(feature = "assembler")]
+pub use assembler::diagnostics::Diagnostic;
+#[cfg(feature = "assembler")]
+pub use assembler::program_parsers::Program;
+pub use bytecode::{Bytecode, LoadError};
+pub use instruction::OpCode;
+pub use vm::{CostTable, ExitReason, VmError, VmErrorKind, VM};
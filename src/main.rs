@@ -1,16 +1,12 @@
-#![warn(clippy)]
-#[macro_use]
-extern crate nom;
+#![warn(clippy::all)]
+extern crate rust_vm;
 
-pub mod vm;
-pub mod instruction;
+pub mod cli;
 pub mod repl;
-pub mod assembler;
 
-use vm::VM;
-use repl::REPL;
+use std::env;
 
 fn main() {
-    let mut repl_instance = REPL::new();
-    repl_instance.run();
+    let args: Vec<String> = env::args().skip(1).collect();
+    std::process::exit(cli::run(&args));
 }
@@ -1,14 +1,15 @@
-use std;
 use std::io;
-use std::io::Write;
+use std::io::{BufRead, Write};
 use std::num::ParseIntError;
 
-use vm::VM;
-use assembler::program_parsers::program;
+use rust_vm::vm::{ExitReason, Location, VM};
+use rust_vm::assembler::Assembler;
 
 #[derive(Default)]
 pub struct REPL {
     command_buffer: Vec<String>,
+    /// The REPL reads commands from and writes to the console of the VM, so
+    /// programs and the REPL share one terminal
     vm: VM,
 }
 
@@ -20,54 +21,102 @@ impl REPL {
         }
     }
 
-    pub fn run(&mut self) {
-        println!("This is an iridium look alike. And this is it's REPL. Enter commands below :");
+    /// A REPL reading commands from `input` and writing its session to `output`
+    pub fn with_io<R: BufRead + 'static, W: Write + 'static>(input: R, output: W) -> REPL {
+        REPL {
+            command_buffer: vec![],
+            vm: VM::new().with_input(input).with_output(output),
+        }
+    }
+
+    /// Runs the session until `.quit` or the end of the input
+    pub fn run(&mut self) -> io::Result<()> {
+        writeln!(self.output(), "This is an iridium look alike. And this is it's REPL. Enter commands below :")?;
         loop {
             let mut buffer = String::new();
 
             // Print ">>> " in front of the command
-            print!(">>> ");
+            write!(self.output(), ">>> ")?;
             // Force flush, otherwise print acts as a buffer until a flush is ordered
             // and then content is shown on screen (terminal)
-            io::stdout().flush().expect("Unable to flush stdout");
+            self.output().flush()?;
 
-            io::stdin()
-                .read_line(&mut buffer)
-                .expect("Unable to read line from user");
+            if self.vm.console_mut().input.read_line(&mut buffer)? == 0 {
+                // end of input, leave the prompt on a line of its own
+                writeln!(self.output())?;
+                return Ok(());
+            }
             let buffer = buffer.trim();
             self.command_buffer.push(buffer.to_string());
 
             match buffer {
                 ".quit" => {
-                    println!("Goodbye then.");
-                    std::process::exit(0);
+                    writeln!(self.output(), "Goodbye then.")?;
+                    return Ok(());
                 }
                 ".history" => {
-                    for command in &self.command_buffer {
-                        println!("{}", command);
-                    }
+                    let history = self.command_buffer.join("\n");
+                    writeln!(self.output(), "{}", history)?;
                 }
                 ".registers" => {
-                    println!("Listing registers and all contents:");
-                    println!("{:#?}", self.vm.registers);
-                    println!("End of Register Listing");
+                    let registers = format!("{:#?}", self.vm.registers);
+                    writeln!(self.output(), "Listing registers and all contents:")?;
+                    writeln!(self.output(), "{}", registers)?;
+                    writeln!(self.output(), "End of Register Listing")?;
+                }
+                _ => self.execute(buffer)?
+            }
+        }
+    }
+
+    /// Assembles `source`, appends it to the program and executes the instructions it assembled to
+    fn execute(&mut self, source: &str) -> io::Result<()> {
+        // labels are relative to where the input lands in the program
+        let mut assembler = Assembler::new();
+        assembler.origin = self.vm.program.len();
+        assembler.data_origin = self.vm.ro_data.len();
+        let mut bytecode = match assembler.assemble_bytecode(source) {
+            Ok(bytecode) => bytecode,
+            Err(diagnostics) => {
+                for diagnostic in diagnostics {
+                    writeln!(self.output(), "{}", diagnostic)?;
                 }
-                _ => {
-                    let prog = match program(buffer.into()) {
-                        Ok((_, prog)) => prog,
-                        Err(_) => {
-                            println!("Error parsing input");
-                            continue;
-                        }
-                    };
-
-                    self.vm.program.append(&mut prog.to_bytes());
-                    self.vm.run_once();
+                return Ok(());
+            }
+        };
+
+        // a line can assemble to several instructions, a wide `load` is LOAD followed by LUI
+        let end = self.vm.program.len() + bytecode.code.len();
+        self.vm.program.append(&mut bytecode.code);
+        self.vm.ro_data.append(&mut bytecode.data);
+        self.vm.symbols.append(&mut bytecode.symbols);
+        let mut result = self.vm.run_once();
+        while result == Ok(ExitReason::Running) && self.vm.pc() < end {
+            result = self.vm.run_once();
+        }
+        match result {
+            Ok(ExitReason::Halted { status: 0 }) => writeln!(self.output(), "HLT encountered"),
+            Ok(ExitReason::Halted { status }) => {
+                writeln!(self.output(), "HLT encountered, exit status {}", status)
+            }
+            Ok(_) => Ok(()),
+            Err(e) => {
+                let location = Location::new(e.pc, &self.vm.symbols);
+                writeln!(self.output(), "VM error: {}", e)?;
+                writeln!(self.output(), "  in {}", location)?;
+                if e.backtrace.is_empty() {
+                    Ok(())
+                } else {
+                    writeln!(self.output(), "{}", e.backtrace)
                 }
             }
         }
     }
 
+    fn output(&mut self) -> &mut dyn Write {
+        &mut *self.vm.console_mut().output
+    }
+
     /// Accepts a hexadecimal string WITHOUT a leading `0x` and returns a Vec of u8
     /// Example for a LOAD command: 00 01 03 E8
     #[allow(dead_code)]
@@ -75,7 +124,7 @@ impl REPL {
         let split = i.split(' ').collect::<Vec<&str>>();
         let mut results: Vec<u8> = vec![];
         for hex_string in split {
-            let byte = u8::from_str_radix(&hex_string, 16);
+            let byte = u8::from_str_radix(hex_string, 16);
             match byte {
                 Ok(result) => {
                     results.push(result);
@@ -88,3 +137,52 @@ impl REPL {
         Ok(results)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+    use rust_vm::vm::SharedBuffer;
+
+    fn testdata(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/repl/testdata").join(name)
+    }
+
+    /// Runs `<name>.in` through a REPL and compares the transcript to `<name>.golden`,
+    /// set UPDATE_GOLDEN to write the transcript instead
+    fn check_session(name: &str) {
+        let input = fs::read(testdata(&format!("{}.in", name))).unwrap();
+        let output = SharedBuffer::new();
+        let mut repl = REPL::with_io(io::Cursor::new(input), output.clone());
+        repl.run().unwrap();
+
+        let golden = testdata(&format!("{}.golden", name));
+        if env::var_os("UPDATE_GOLDEN").is_some() {
+            fs::write(&golden, output.contents()).unwrap();
+        }
+        assert_eq!(fs::read_to_string(&golden).unwrap(), output.to_string_lossy());
+    }
+
+    #[test]
+    fn test_session() {
+        check_session("session");
+    }
+
+    #[test]
+    fn test_session_ends_with_input() {
+        let output = SharedBuffer::new();
+        let mut repl = REPL::with_io(&b"hlt"[..], output.clone());
+        repl.run().unwrap();
+        assert!(output.to_string_lossy().ends_with(">>> HLT encountered\n>>> \n"));
+    }
+
+    #[test]
+    fn test_wide_load_runs_on_its_line() {
+        let output = SharedBuffer::new();
+        let mut repl = REPL::with_io(&b"load $0 #-1\nprti $0\nhlt\n"[..], output.clone());
+        repl.run().unwrap();
+        assert!(output.to_string_lossy().ends_with(">>> >>> -1>>> HLT encountered\n>>> \n"));
+    }
+}
@@ -0,0 +1,76 @@
+use core::fmt;
+
+use bytecode::{Bytecode, DebugSymbol};
+use prelude::*;
+
+/// An address in the code section, resolved to the label it falls under when symbols are known
+#[derive(Debug, PartialEq, Clone)]
+pub struct Location {
+  pub address: usize,
+  pub label: Option<String>,
+  /// Distance from `label` to `address`
+  pub offset: usize,
+}
+
+impl Location {
+  pub fn new(address: usize, symbols: &[DebugSymbol]) -> Location {
+    match Bytecode::symbolize(symbols, address) {
+      Some((label, offset)) => Location { address, label: Some(label.to_string()), offset },
+      None => Location { address, label: None, offset: 0 }
+    }
+  }
+}
+
+impl fmt::Display for Location {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match &self.label {
+      Some(label) if self.offset == 0 => write!(f, "{} ({})", self.address, label),
+      Some(label) => write!(f, "{} ({}+{})", self.address, label, self.offset),
+      None => write!(f, "{}", self.address),
+    }
+  }
+}
+
+/// The active calls when the VM trapped
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Backtrace {
+  /// Return addresses on the call stack, innermost call first
+  pub frames: Vec<Location>,
+}
+
+impl Backtrace {
+  pub fn is_empty(&self) -> bool {
+    self.frames.is_empty()
+  }
+}
+
+impl fmt::Display for Backtrace {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "backtrace:")?;
+    for (depth, frame) in self.frames.iter().enumerate() {
+      write!(f, "\n  {}: returns to {}", depth, frame)?;
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use bytecode::SymbolSection;
+
+  #[test]
+  fn test_display() {
+    let symbols = vec![
+      DebugSymbol::new("main".to_string(), SymbolSection::Code, 0),
+      DebugSymbol::new("helper".to_string(), SymbolSection::Code, 10),
+    ];
+    let backtrace = Backtrace {
+      frames: vec![Location::new(13, &symbols), Location::new(10, &symbols), Location::new(3, &[])]
+    };
+    assert_eq!(
+      "backtrace:\n  0: returns to 13 (helper+3)\n  1: returns to 10 (helper)\n  2: returns to 3",
+      backtrace.to_string()
+    );
+  }
+}
@@ -0,0 +1,57 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::io;
+use std::io::{BufRead, Write};
+use std::rc::Rc;
+
+/// Where the console I/O instructions write to and read from, stdout and stdin by default
+pub struct Console {
+  pub output: Box<dyn Write>,
+  pub input: Box<dyn BufRead>,
+}
+
+impl Default for Console {
+  fn default() -> Console {
+    Console {
+      output: Box::new(io::stdout()),
+      input: Box::new(io::BufReader::new(io::stdin())),
+    }
+  }
+}
+
+impl fmt::Debug for Console {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "Console")
+  }
+}
+
+/// Output buffer that stays readable after a clone of it is handed to a VM
+#[derive(Debug, Default, Clone)]
+pub struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl SharedBuffer {
+  pub fn new() -> SharedBuffer {
+    SharedBuffer::default()
+  }
+
+  /// Everything written so far
+  pub fn contents(&self) -> Vec<u8> {
+    self.0.borrow().clone()
+  }
+
+  /// Everything written so far, with invalid UTF-8 replaced
+  pub fn to_string_lossy(&self) -> String {
+    String::from_utf8_lossy(&self.0.borrow()).into_owned()
+  }
+}
+
+impl Write for SharedBuffer {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    self.0.borrow_mut().extend_from_slice(buf);
+    Ok(buf.len())
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    Ok(())
+  }
+}
@@ -0,0 +1,75 @@
+use instruction::Instruction;
+use prelude::*;
+
+/// Marks byte offsets no decoded instruction starts at
+const NOT_DECODED: u32 = u32::MAX;
+
+/// A program decoded once when it is loaded, so running it does not decode the same bytes
+/// again on every pass through a loop
+#[derive(Debug, Default)]
+pub struct DecodedProgram {
+  instructions: Vec<Instruction>,
+  /// Index into `instructions` of the instruction starting at each byte offset
+  index: Vec<u32>,
+}
+
+impl DecodedProgram {
+  /// Decodes `code` from the start, skipping a byte where an instruction does not decode so
+  /// executing it reports the error
+  pub fn new(code: &[u8]) -> DecodedProgram {
+    let mut instructions = vec![];
+    let mut index = vec![NOT_DECODED; code.len()];
+    let mut offset = 0;
+    while offset < code.len() {
+      match Instruction::decode(code, offset) {
+        Ok(instruction) => {
+          index[offset] = instructions.len() as u32;
+          instructions.push(instruction);
+          offset += instruction.size as usize;
+        }
+        Err(_) => offset += 1
+      }
+    }
+    DecodedProgram { instructions, index }
+  }
+
+  /// The instruction decoded at `offset`, `None` when there is none or when the program is no
+  /// longer `code_len` bytes long, having changed since it was decoded
+  #[inline]
+  pub fn get(&self, offset: usize, code_len: usize) -> Option<&Instruction> {
+    if self.index.len() != code_len {
+      return None;
+    }
+    match self.index.get(offset) {
+      Some(&NOT_DECODED) | None => None,
+      Some(&position) => self.instructions.get(position as usize)
+    }
+  }
+
+  pub fn len(&self) -> usize {
+    self.instructions.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.instructions.is_empty()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use instruction::OpCode;
+
+  #[test]
+  fn test_decoded_program() {
+    let code = [OpCode::INC as u8, 1, 255, OpCode::LOAD as u8, 0, 0, 7, OpCode::HLT as u8];
+    let decoded = DecodedProgram::new(&code);
+    assert_eq!(3, decoded.len());
+    assert_eq!(Some(OpCode::LOAD), decoded.get(3, code.len()).map(|instruction| instruction.opcode));
+    assert_eq!([0, 7, 0], decoded.get(3, code.len()).unwrap().operands);
+    // inside an instruction, an undecodable byte and a program that changed since
+    assert_eq!(None, decoded.get(1, code.len()));
+    assert_eq!(None, decoded.get(2, code.len()));
+    assert_eq!(None, decoded.get(0, code.len() + 1));
+  }
+}
@@ -0,0 +1,113 @@
+use core::error::Error;
+use core::fmt;
+
+use prelude::*;
+use vm::backtrace::Backtrace;
+
+/// Typed cause of a VM fault
+#[derive(Debug, PartialEq, Clone)]
+pub enum VmErrorKind {
+  /// The opcode byte does not map to any known instruction
+  IllegalOpcode,
+  /// An operand names a register outside of the register file
+  RegisterOutOfRange { register: u8 },
+  /// The program ends in the middle of an instruction's operands
+  TruncatedInstruction,
+  /// A jump would move the program counter outside of the program
+  BadJumpTarget { target: i64 },
+  /// An arithmetic result does not fit in a register and the VM traps on overflow
+  ArithmeticOverflow,
+  /// A DIV instruction was given a zero divisor
+  DivisionByZero,
+  /// An access or allocation of `size` bytes at `address` falls outside the heap
+  HeapFault { address: i64, size: i64 },
+  /// ALOC would grow the heap past its maximum size
+  OutOfMemory { requested: i64 },
+  /// FREE was given an address that is not the start of an allocated block
+  InvalidFree { address: i64 },
+  /// A PUSH or CALL found the stack full, `depth` is its size in slots
+  StackOverflow { depth: usize },
+  /// A POP or RET found the stack empty
+  StackUnderflow,
+  /// SYSCALL was given a number no host function is registered for
+  UnknownHostFunction { number: u16 },
+  /// A host function reported a failure of its own
+  HostFunctionFailed { message: String },
+  /// Reading from or writing to the console failed
+  Io { message: String },
+  /// An input instruction found no more input
+  EndOfInput,
+  /// READI read a line that is not an integer
+  BadInput { input: String },
+  /// A store targets the read-only data section
+  ReadOnlyFault { address: i64 },
+}
+
+impl fmt::Display for VmErrorKind {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      VmErrorKind::IllegalOpcode => write!(f, "illegal opcode"),
+      VmErrorKind::RegisterOutOfRange { register } => {
+        write!(f, "register ${} is out of range", register)
+      }
+      VmErrorKind::TruncatedInstruction => write!(f, "truncated instruction"),
+      VmErrorKind::BadJumpTarget { target } => write!(f, "bad jump target {}", target),
+      VmErrorKind::ArithmeticOverflow => write!(f, "arithmetic overflow"),
+      VmErrorKind::DivisionByZero => write!(f, "division by zero"),
+      VmErrorKind::HeapFault { address, size } => {
+        write!(f, "heap fault accessing {} byte(s) at {}", size, address)
+      }
+      VmErrorKind::OutOfMemory { requested } => {
+        write!(f, "out of memory allocating {} byte(s)", requested)
+      }
+      VmErrorKind::InvalidFree { address } => {
+        write!(f, "free of {} which is not an allocated block", address)
+      }
+      VmErrorKind::StackOverflow { depth } => {
+        write!(f, "stack overflow, the stack holds {} slots", depth)
+      }
+      VmErrorKind::StackUnderflow => write!(f, "stack underflow"),
+      VmErrorKind::UnknownHostFunction { number } => {
+        write!(f, "no host function is registered as syscall {}", number)
+      }
+      VmErrorKind::HostFunctionFailed { message } => write!(f, "host function failed: {}", message),
+      VmErrorKind::Io { message } => write!(f, "console i/o failed: {}", message),
+      VmErrorKind::EndOfInput => write!(f, "end of input"),
+      VmErrorKind::BadInput { input } => write!(f, "expected an integer, read {:?}", input),
+      VmErrorKind::ReadOnlyFault { address } => {
+        write!(f, "write to read-only data at {}", address)
+      }
+    }
+  }
+}
+
+/// Error returned by the VM when an instruction cannot be executed
+#[derive(Debug, PartialEq, Clone)]
+pub struct VmError {
+  /// Address of the faulting instruction's opcode byte
+  pub pc: usize,
+  /// Raw opcode byte of the faulting instruction
+  pub opcode: u8,
+  pub kind: VmErrorKind,
+  /// Calls that were active when the VM trapped
+  pub backtrace: Backtrace,
+}
+
+impl VmError {
+  pub fn new(pc: usize, opcode: u8, kind: VmErrorKind) -> VmError {
+    VmError { pc, opcode, kind, backtrace: Backtrace::default() }
+  }
+
+  pub fn with_backtrace(mut self, backtrace: Backtrace) -> VmError {
+    self.backtrace = backtrace;
+    self
+  }
+}
+
+impl fmt::Display for VmError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{} at pc {} (opcode {:#04x})", self.kind, self.pc, self.opcode)
+  }
+}
+
+impl Error for VmError {}
@@ -0,0 +1,64 @@
+/// Status flags updated by arithmetic and comparison instructions
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub struct Flags {
+  /// The last result was zero
+  pub zero: bool,
+  /// The last result had its sign bit set
+  pub negative: bool,
+  /// The last operation produced an unsigned carry or borrow
+  pub carry: bool,
+  /// The last operation overflowed as a signed operation
+  pub overflow: bool,
+  /// Whether the relation the last comparison opcode tested holds: EQ, NEQ, GT, LT, GTE and LTE
+  /// set it to their outcome and CMP to whether its operands are equal, JEQ jumps when it is set
+  /// and JNEQ when it is clear
+  pub condition: bool,
+}
+
+impl Flags {
+  /// Sets zero, negative, carry and overflow from the result of an operation
+  pub fn set_result(&mut self, result: i32, carry: bool, overflow: bool) {
+    self.zero = result == 0;
+    self.negative = result < 0;
+    self.carry = carry;
+    self.overflow = overflow;
+  }
+
+  /// Sets the flags as if `value2` was subtracted from `value1`
+  pub fn compare(&mut self, value1: i32, value2: i32) {
+    let (difference, overflow) = value1.overflowing_sub(value2);
+    let borrow = (value1 as u32) < (value2 as u32);
+    self.set_result(difference, borrow, overflow);
+  }
+
+  /// Signed "less than" after a comparison or subtraction
+  pub fn less(&self) -> bool {
+    self.negative != self.overflow
+  }
+
+  /// Signed "greater than" after a comparison or subtraction
+  pub fn greater(&self) -> bool {
+    !self.zero && !self.less()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_compare() {
+    let mut flags = Flags::default();
+    flags.compare(3, 3);
+    assert!(flags.zero && !flags.less() && !flags.greater());
+    flags.compare(-1, 1);
+    assert!(flags.less() && !flags.carry);
+    flags.compare(1, -1);
+    assert!(flags.greater() && flags.carry);
+    // the subtraction overflows but the signed ordering is still right
+    flags.compare(i32::MIN, 1);
+    assert!(flags.overflow && flags.less());
+    flags.compare(i32::MAX, -1);
+    assert!(flags.overflow && flags.greater());
+  }
+}
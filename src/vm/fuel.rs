@@ -0,0 +1,58 @@
+use instruction::OpCode;
+
+/// Number of entries in a cost table, every opcode plus IGL
+const COST_COUNT: usize = OpCode::IGL as usize + 1;
+
+/// Fuel each opcode uses up when `VM::run_with_budget` executes it, 1 for every opcode unless
+/// changed
+///
+/// An opcode costing 0 runs for free, a loop of such instructions never runs out of fuel.
+#[derive(Debug, PartialEq, Clone)]
+pub struct CostTable {
+  costs: [u32; COST_COUNT],
+}
+
+impl Default for CostTable {
+  fn default() -> CostTable {
+    CostTable::uniform(1)
+  }
+}
+
+impl CostTable {
+  pub fn new() -> CostTable {
+    CostTable::default()
+  }
+
+  /// A table where every opcode costs `cost`
+  pub fn uniform(cost: u32) -> CostTable {
+    CostTable { costs: [cost; COST_COUNT] }
+  }
+
+  pub fn set(&mut self, opcode: OpCode, cost: u32) {
+    self.costs[opcode as usize] = cost;
+  }
+
+  /// Chainable form of `set`
+  pub fn with(mut self, opcode: OpCode, cost: u32) -> CostTable {
+    self.set(opcode, cost);
+    self
+  }
+
+  pub fn cost(&self, opcode: OpCode) -> u32 {
+    self.costs[opcode as usize]
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_cost_table() {
+    let table = CostTable::new().with(OpCode::SYSCALL, 50).with(OpCode::IGL, 0);
+    assert_eq!(1, table.cost(OpCode::LOAD));
+    assert_eq!(50, table.cost(OpCode::SYSCALL));
+    assert_eq!(0, table.cost(OpCode::IGL));
+    assert_eq!(3, CostTable::uniform(3).cost(OpCode::EXIT));
+  }
+}
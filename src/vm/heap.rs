@@ -0,0 +1,182 @@
+use prelude::*;
+use vm::error::VmErrorKind;
+
+/// Heap size limit of a VM that was not given one
+pub const DEFAULT_MAX_HEAP_SIZE: usize = 16 * 1024 * 1024;
+
+/// A run of heap bytes, offsets are relative to the start of the heap
+#[derive(Debug, PartialEq, Clone, Copy)]
+struct Block {
+  start: usize,
+  size: usize,
+}
+
+impl Block {
+  fn end(&self) -> usize {
+    self.start + self.size
+  }
+}
+
+/// Heap memory handed out by ALOC, with a first fit free list so FREEd blocks get reused
+#[derive(Debug)]
+pub struct Heap {
+  bytes: Vec<u8>,
+  /// Blocks currently allocated
+  used: Vec<Block>,
+  /// Freed blocks sorted by start, neighbours are merged
+  free: Vec<Block>,
+  max_size: usize,
+}
+
+impl Default for Heap {
+  fn default() -> Heap {
+    Heap::new(DEFAULT_MAX_HEAP_SIZE)
+  }
+}
+
+impl Heap {
+  pub fn new(max_size: usize) -> Heap {
+    Heap {
+      bytes: vec![],
+      used: vec![],
+      free: vec![],
+      max_size
+    }
+  }
+
+  pub fn bytes(&self) -> &[u8] {
+    &self.bytes
+  }
+
+  pub fn bytes_mut(&mut self) -> &mut [u8] {
+    &mut self.bytes
+  }
+
+  pub fn len(&self) -> usize {
+    self.bytes.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.bytes.is_empty()
+  }
+
+  pub fn max_size(&self) -> usize {
+    self.max_size
+  }
+
+  /// Reserves `size` zeroed bytes and returns the offset of the first one
+  pub fn allocate(&mut self, size: usize) -> Result<usize, VmErrorKind> {
+    // empty blocks still get an address of their own
+    let size = size.max(1);
+    let start = match self.free.iter().position(|block| block.size >= size) {
+      Some(index) => {
+        let block = self.free[index];
+        if block.size == size {
+          self.free.remove(index);
+        } else {
+          self.free[index] = Block { start: block.start + size, size: block.size - size };
+        }
+        block.start
+      }
+      None => {
+        // a free block at the end of the heap only needs to grow
+        let start = match self.free.last() {
+          Some(block) if block.end() == self.bytes.len() => block.start,
+          _ => self.bytes.len()
+        };
+        let end = match start.checked_add(size) {
+          Some(end) if end <= self.max_size => end,
+          _ => return Err(VmErrorKind::OutOfMemory { requested: size.min(i64::MAX as usize) as i64 })
+        };
+        if start < self.bytes.len() {
+          self.free.pop();
+        }
+        self.bytes.resize(end, 0);
+        start
+      }
+    };
+    for byte in &mut self.bytes[start..start + size] {
+      *byte = 0;
+    }
+    self.used.push(Block { start, size });
+    Ok(start)
+  }
+
+  /// Returns the block starting at `start` to the free list
+  pub fn free(&mut self, start: usize) -> Result<(), VmErrorKind> {
+    let index = self.used
+      .iter()
+      .position(|block| block.start == start)
+      .ok_or(VmErrorKind::InvalidFree { address: start as i64 })?;
+    let mut block = self.used.swap_remove(index);
+    let index = self.free.iter().position(|free| free.start > block.start).unwrap_or(self.free.len());
+    if index < self.free.len() && block.end() == self.free[index].start {
+      block.size += self.free.remove(index).size;
+    }
+    if index > 0 && self.free[index - 1].end() == block.start {
+      self.free[index - 1].size += block.size;
+    } else {
+      self.free.insert(index, block);
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_allocate_grows_heap() {
+    let mut heap = Heap::default();
+    assert_eq!(Ok(0), heap.allocate(4));
+    assert_eq!(Ok(4), heap.allocate(2));
+    assert_eq!(6, heap.len());
+  }
+
+  #[test]
+  fn test_free_blocks_are_reused_and_merged() {
+    let mut heap = Heap::default();
+    let first = heap.allocate(4).unwrap();
+    let second = heap.allocate(4).unwrap();
+    heap.allocate(4).unwrap();
+    heap.bytes_mut()[first] = 7;
+    heap.free(first).unwrap();
+    heap.free(second).unwrap();
+    assert_eq!(vec![Block { start: 0, size: 8 }], heap.free);
+
+    assert_eq!(Ok(0), heap.allocate(6));
+    assert_eq!(0, heap.bytes()[first]);
+    assert_eq!(Ok(6), heap.allocate(2));
+    assert_eq!(12, heap.len());
+  }
+
+  #[test]
+  fn test_trailing_free_block_grows() {
+    let mut heap = Heap::default();
+    heap.allocate(4).unwrap();
+    let last = heap.allocate(4).unwrap();
+    heap.free(last).unwrap();
+    assert_eq!(Ok(4), heap.allocate(10));
+    assert_eq!(14, heap.len());
+    assert!(heap.free.is_empty());
+  }
+
+  #[test]
+  fn test_out_of_memory() {
+    let mut heap = Heap::new(8);
+    heap.allocate(6).unwrap();
+    assert_eq!(Err(VmErrorKind::OutOfMemory { requested: 4 }), heap.allocate(4));
+    assert_eq!(Err(VmErrorKind::OutOfMemory { requested: i64::MAX }), heap.allocate(usize::MAX));
+    assert_eq!(6, heap.len());
+  }
+
+  #[test]
+  fn test_invalid_free() {
+    let mut heap = Heap::default();
+    heap.allocate(4).unwrap();
+    assert_eq!(Err(VmErrorKind::InvalidFree { address: 2 }), heap.free(2));
+    heap.free(0).unwrap();
+    assert_eq!(Err(VmErrorKind::InvalidFree { address: 0 }), heap.free(0));
+  }
+}
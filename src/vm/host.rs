@@ -0,0 +1,97 @@
+use core::fmt;
+
+use prelude::*;
+use vm::VM;
+use vm::error::VmErrorKind;
+
+/// A Rust function programs call with SYSCALL, it reads its arguments from and writes its
+/// results to the VM registers and memory, an error stops the program
+///
+/// Functions report failures of their own with `VmErrorKind::HostFunctionFailed`.
+pub type HostFunction = Box<dyn FnMut(&mut VM) -> Result<(), VmErrorKind>>;
+
+/// Host functions callable from programs, keyed by the number SYSCALL is given
+#[derive(Default)]
+pub struct HostFunctions {
+  functions: Vec<(u16, String, HostFunction)>,
+}
+
+impl HostFunctions {
+  pub fn new() -> HostFunctions {
+    HostFunctions { functions: vec![] }
+  }
+
+  /// Makes `function` callable as `syscall #number`, replacing any function with that number
+  pub fn register<F>(&mut self, number: u16, name: &str, function: F)
+    where F: FnMut(&mut VM) -> Result<(), VmErrorKind> + 'static
+  {
+    self.functions.retain(|(registered, _, _)| *registered != number);
+    self.functions.push((number, name.to_string(), Box::new(function)));
+  }
+
+  /// Chainable form of `register`
+  pub fn with<F>(mut self, number: u16, name: &str, function: F) -> HostFunctions
+    where F: FnMut(&mut VM) -> Result<(), VmErrorKind> + 'static
+  {
+    self.register(number, name, function);
+    self
+  }
+
+  /// Number of the function registered as `name`
+  pub fn number_of(&self, name: &str) -> Option<u16> {
+    self.functions
+      .iter()
+      .find(|(_, registered, _)| registered == name)
+      .map(|(number, _, _)| *number)
+  }
+
+  pub fn name_of(&self, number: u16) -> Option<&str> {
+    self.functions
+      .iter()
+      .find(|(registered, _, _)| *registered == number)
+      .map(|(_, name, _)| name.as_str())
+  }
+
+  /// Moves the functions of `other` in, replacing the ones registered under the same numbers
+  pub(crate) fn merge(&mut self, other: HostFunctions) {
+    for (number, name, function) in other.functions {
+      self.functions.retain(|(registered, _, _)| *registered != number);
+      self.functions.push((number, name, function));
+    }
+  }
+
+  pub(crate) fn get_mut(&mut self, number: u16) -> Option<&mut HostFunction> {
+    self.functions
+      .iter_mut()
+      .find(|(registered, _, _)| *registered == number)
+      .map(|(_, _, function)| function)
+  }
+}
+
+impl fmt::Debug for HostFunctions {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_map()
+      .entries(self.functions.iter().map(|(number, name, _)| (number, name)))
+      .finish()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_registry() {
+    let mut functions = HostFunctions::new()
+      .with(1, "log", |_| Ok(()))
+      .with(2, "time", |_| Ok(()));
+    assert_eq!(Some(2), functions.number_of("time"));
+    assert_eq!(Some("log"), functions.name_of(1));
+
+    functions.register(1, "trace", |_| Err(VmErrorKind::StackUnderflow));
+    assert_eq!(None, functions.number_of("log"));
+    let mut vm = VM::new();
+    assert_eq!(Err(VmErrorKind::StackUnderflow), functions.get_mut(1).unwrap()(&mut vm));
+    assert!(functions.get_mut(3).is_none());
+  }
+}
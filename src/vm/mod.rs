@@ -0,0 +1,1888 @@
+pub mod backtrace;
+#[cfg(feature = "std")]
+pub mod console;
+pub mod decoded;
+pub mod error;
+pub mod flags;
+pub mod fuel;
+pub mod heap;
+pub mod host;
+pub mod stack;
+
+#[cfg(feature = "std")]
+use std::io::{BufRead, Write};
+
+use core::convert::TryFrom;
+
+use bytecode::{Bytecode, DebugSymbol, LoadError};
+use instruction::{Instruction, OpCode, REGISTER_COUNT};
+use prelude::*;
+pub use self::backtrace::{Backtrace, Location};
+#[cfg(feature = "std")]
+pub use self::console::{Console, SharedBuffer};
+pub use self::decoded::DecodedProgram;
+pub use self::error::{VmError, VmErrorKind};
+pub use self::flags::Flags;
+pub use self::fuel::CostTable;
+pub use self::heap::Heap;
+pub use self::host::{HostFunction, HostFunctions};
+pub use self::stack::Stack;
+
+/// Outcome of a successful `run` or `run_once` call
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ExitReason {
+  /// An instruction was executed and the program can keep going
+  Running,
+  /// A HLT instruction was executed, `status` is the exit status it gave, 0 for a bare `hlt`
+  Halted { status: i32 },
+  /// The program counter reached the end of the program
+  EndOfProgram,
+  /// `run_with_budget` ran out of fuel before the next instruction, which has not run yet,
+  /// another budget resumes from it
+  OutOfFuel,
+}
+
+impl ExitReason {
+  /// Exit status of a finished program, running off the end of it counts as success
+  pub fn status(self) -> Option<i32> {
+    match self {
+      ExitReason::Running | ExitReason::OutOfFuel => None,
+      ExitReason::Halted { status } => Some(status),
+      ExitReason::EndOfProgram => Some(0),
+    }
+  }
+}
+
+/// How arithmetic instructions behave when their result does not fit in a register
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub enum OverflowMode {
+  /// Keep the low 32 bits of the result (two's complement wrap around)
+  #[default]
+  Wrapping,
+  /// Stop the program with an `ArithmeticOverflow` error
+  Trap,
+  /// Clamp the result to `i32::MIN` or `i32::MAX`
+  Saturating,
+}
+
+/// Memory instructions see one address space: the read-only data section starts at
+/// address 0 and the heap follows right after it
+#[derive(Debug,Default)]
+pub struct VM {
+  pub registers: [i32; REGISTER_COUNT],
+  pc: usize,
+  /// Code of the program, changing its bytes in place after `load_program` needs `predecode`
+  /// to be called again
+  pub program: Vec<u8>,
+  /// Read-only data section of the loaded program file
+  pub ro_data: Vec<u8>,
+  /// Labels of the loaded program, used to make backtraces readable
+  pub symbols: Vec<DebugSymbol>,
+  /// `program` as decoded by `predecode`
+  decoded: DecodedProgram,
+  heap: Heap,
+  stack: Stack,
+  host_functions: HostFunctions,
+  #[cfg(feature = "std")]
+  console: Console,
+  /// What `run_with_budget` charges for each instruction
+  fuel_costs: CostTable,
+  /// Fuel left of the budget given to `run_with_budget`
+  fuel: u64,
+  /// Remainder of the last DIV, it has the sign of the dividend
+  remainder: i32,
+  flags: Flags,
+  overflow_mode: OverflowMode
+}
+
+impl VM {
+  pub fn new() -> VM {
+    VM {
+      registers: [0; REGISTER_COUNT],
+      pc: 0,
+      program: vec![],
+      ro_data: vec![],
+      symbols: vec![],
+      decoded: DecodedProgram::default(),
+      heap: Heap::default(),
+      stack: Stack::default(),
+      host_functions: HostFunctions::new(),
+      #[cfg(feature = "std")]
+      console: Console::default(),
+      fuel_costs: CostTable::default(),
+      fuel: 0,
+      remainder: 0,
+      flags: Flags::default(),
+      overflow_mode: OverflowMode::Wrapping
+    }
+  }
+
+  /// Selects how arithmetic instructions handle results that do not fit in a register
+  pub fn with_overflow_mode(mut self, mode: OverflowMode) -> VM {
+    self.overflow_mode = mode;
+    self
+  }
+
+  /// Caps the heap ALOC can grow, allocations past it fail with an `OutOfMemory` error, as do
+  /// blocks whose address does not fit in a register
+  pub fn with_max_heap_size(mut self, bytes: usize) -> VM {
+    self.heap = Heap::new(bytes);
+    self
+  }
+
+  /// Sets how many values and return addresses the stack holds before PUSH and CALL overflow
+  pub fn with_stack_depth(mut self, slots: usize) -> VM {
+    self.stack = Stack::new(slots);
+    self
+  }
+
+  /// Sets the fuel `run_with_budget` charges for each opcode
+  pub fn with_fuel_costs(mut self, costs: CostTable) -> VM {
+    self.fuel_costs = costs;
+    self
+  }
+
+  /// Functions programs can call into with SYSCALL
+  pub fn with_host_functions(mut self, functions: HostFunctions) -> VM {
+    self.host_functions = functions;
+    self
+  }
+
+  pub fn host_functions_mut(&mut self) -> &mut HostFunctions {
+    &mut self.host_functions
+  }
+
+  /// Sends what the print instructions write to `output` instead of stdout
+  #[cfg(feature = "std")]
+  pub fn with_output<W: Write + 'static>(mut self, output: W) -> VM {
+    self.console.output = Box::new(output);
+    self
+  }
+
+  /// Makes the read instructions read from `input` instead of stdin
+  #[cfg(feature = "std")]
+  pub fn with_input<R: BufRead + 'static>(mut self, input: R) -> VM {
+    self.console.input = Box::new(input);
+    self
+  }
+
+  /// Address of the next instruction to execute
+  pub fn pc(&self) -> usize {
+    self.pc
+  }
+
+  /// The streams the console I/O instructions use
+  #[cfg(feature = "std")]
+  pub fn console_mut(&mut self) -> &mut Console {
+    &mut self.console
+  }
+
+  /// Validates a program file and makes it the program to run, starting at its entry point
+  pub fn load_program(&mut self, bytes: &[u8]) -> Result<(), LoadError> {
+    let bytecode = Bytecode::from_bytes(bytes)?;
+    self.program = bytecode.code;
+    self.ro_data = bytecode.data;
+    self.symbols = bytecode.symbols;
+    self.pc = bytecode.entry_point as usize;
+    self.predecode();
+    Ok(())
+  }
+
+  /// Decodes `program` ahead of running it, `load_program` does this for the programs it loads
+  ///
+  /// Code that was not decoded, like code appended to `program` since, is decoded as it runs.
+  pub fn predecode(&mut self) {
+    self.decoded = DecodedProgram::new(&self.program);
+  }
+
+  /// Executes instructions until the program halts, runs out or faults
+  pub fn run(&mut self) -> Result<ExitReason, VmError> {
+    loop {
+      match self.run_once()? {
+        ExitReason::Running => {},
+        reason => return Ok(reason)
+      }
+    }
+  }
+
+  /// Executes instructions like `run`, charging each one its cost from the cost table before it
+  /// runs, and stops with `ExitReason::OutOfFuel` when the next one costs more than is left
+  pub fn run_with_budget(&mut self, fuel: u64) -> Result<ExitReason, VmError> {
+    self.fuel = fuel;
+    loop {
+      if let Some(&code) = self.program.get(self.pc) {
+        let cost = u64::from(self.fuel_costs.cost(OpCode::from(code)));
+        if cost > self.fuel {
+          return Ok(ExitReason::OutOfFuel);
+        }
+        self.fuel -= cost;
+      }
+      match self.run_once()? {
+        ExitReason::Running => {},
+        reason => return Ok(reason)
+      }
+    }
+  }
+
+  /// Fuel left of the budget given to the last `run_with_budget`
+  pub fn fuel(&self) -> u64 {
+    self.fuel
+  }
+
+  /// Executes a single instruction
+  pub fn run_once(&mut self) -> Result<ExitReason, VmError> {
+    let start = self.pc;
+    self.execute_instruction().map_err(|kind| {
+      VmError::new(start, self.program[start], kind).with_backtrace(self.backtrace())
+    })
+  }
+
+  /// Allocates `size` zeroed heap bytes and returns the address of the first one
+  pub fn allocate(&mut self, size: usize) -> Result<i64, VmErrorKind> {
+    let start = self.heap.allocate(size)?;
+    Ok(self.heap_address(start))
+  }
+
+  /// The `size` bytes at `address` in the data section or the heap
+  pub fn read_bytes(&self, address: i64, size: usize) -> Result<&[u8], VmErrorKind> {
+    let fault = VmErrorKind::HeapFault { address, size: size.min(i64::MAX as usize) as i64 };
+    let start = usize::try_from(address).map_err(|_| fault.clone())?;
+    let end = start.checked_add(size).ok_or_else(|| fault.clone())?;
+    let data_len = self.ro_data.len();
+    if end <= data_len {
+      Ok(&self.ro_data[start..end])
+    } else if start >= data_len && end - data_len <= self.heap.len() {
+      Ok(&self.heap.bytes()[start - data_len..end - data_len])
+    } else {
+      Err(fault)
+    }
+  }
+
+  /// Copies `bytes` to the heap at `address`
+  pub fn write_bytes(&mut self, address: i64, bytes: &[u8]) -> Result<(), VmErrorKind> {
+    let data_len = self.ro_data.len() as i64;
+    if address >= 0 && address < data_len {
+      return Err(VmErrorKind::ReadOnlyFault { address });
+    }
+    let fault = VmErrorKind::HeapFault { address, size: bytes.len() as i64 };
+    let start = address
+      .checked_sub(data_len)
+      .and_then(|start| usize::try_from(start).ok())
+      .ok_or_else(|| fault.clone())?;
+    let end = start.checked_add(bytes.len()).filter(|&end| end <= self.heap.len()).ok_or(fault)?;
+    self.heap.bytes_mut()[start..end].copy_from_slice(bytes);
+    Ok(())
+  }
+
+  /// The calls currently active, with return addresses resolved against `symbols`
+  pub fn backtrace(&self) -> Backtrace {
+    let frames = self.stack
+      .return_addresses()
+      .into_iter()
+      .map(|address| Location::new(address as usize, &self.symbols))
+      .collect();
+    Backtrace { frames }
+  }
+
+  pub fn add_byte(&mut self, byte : u8) {
+    self.program.push(byte);
+  }
+
+  fn execute_instruction(&mut self) -> Result<ExitReason, VmErrorKind> {
+    if self.pc >= self.program.len() {
+      // program counter above program length, we're done
+      return Ok(ExitReason::EndOfProgram);
+    }
+
+    // instructions the load time pass did not decode, like jump targets inside another
+    // instruction or code added since, are decoded as they run
+    let instruction = match self.decoded.get(self.pc, self.program.len()) {
+      Some(instruction) => *instruction,
+      None => match Instruction::decode(&self.program, self.pc) {
+        Ok(instruction) => instruction,
+        Err(kind) => {
+          // the opcode byte is used up either way
+          self.pc += 1;
+          return Err(kind);
+        }
+      }
+    };
+    self.pc += instruction.size as usize;
+    match instruction.opcode {
+      OpCode::LOAD => {
+        // Where the value must be loaded
+        let register = instruction.register(0);
+        // the value to load
+        let number = instruction.integer(1);
+        self.registers[register] = number as i32;
+      },
+      OpCode::ADD => {
+        // 1st value
+        let value1 = self.register_value(&instruction, 0);
+        // 2nd value
+        let value2 = self.register_value(&instruction, 1);
+        // store result in the register at location from 3rd operand
+        let destination = instruction.register(2);
+        self.registers[destination] = self.add(value1, value2)?;
+      },
+      OpCode::SUB => {
+        let value1 = self.register_value(&instruction, 0);
+        let value2 = self.register_value(&instruction, 1);
+        let destination = instruction.register(2);
+        self.registers[destination] = self.sub(value1, value2)?;
+      },
+      OpCode::MUL => {
+        let value1 = self.register_value(&instruction, 0);
+        let value2 = self.register_value(&instruction, 1);
+        let destination = instruction.register(2);
+        let (wrapped, overflow) = value1.overflowing_mul(value2);
+        self.registers[destination] = self.apply_overflow(
+          wrapped, overflow, overflow, value1.saturating_mul(value2)
+        )?;
+      },
+      OpCode::DIV => {
+        let value1 = self.register_value(&instruction, 0);
+        let value2 = self.register_value(&instruction, 1);
+        let destination = instruction.register(2);
+        if value2 == 0 {
+          return Err(VmErrorKind::DivisionByZero);
+        }
+        // integer division (get the integer part), only i32::MIN / -1 can overflow
+        let (wrapped, overflow) = value1.overflowing_div(value2);
+        self.registers[destination] = self.apply_overflow(wrapped, overflow, false, i32::MAX)?;
+        // remainder
+        self.remainder = value1.wrapping_rem(value2);
+      },
+      OpCode::HLT => {
+        return Ok(ExitReason::Halted { status: 0 });
+      },
+      OpCode::EXIT => {
+        let status = self.register_value(&instruction, 0);
+        return Ok(ExitReason::Halted { status });
+      },
+      OpCode::JMP => {
+        let target = self.register_value(&instruction, 0);
+        self.jump_to(i64::from(target))?;
+      },
+      OpCode::JMPF => {
+        let target = self.register_value(&instruction, 0);
+        let pc = self.pc as i64;
+        self.jump_to(pc + i64::from(target))?;
+      },
+      OpCode::JMPB => {
+        let target = self.register_value(&instruction, 0);
+        let pc = self.pc as i64;
+        self.jump_to(pc - i64::from(target))?;
+      },
+      OpCode::EQ => {
+        let (value1, value2) = self.compare_registers(&instruction);
+        self.flags.condition = value1 == value2;
+      },
+      OpCode::NEQ => {
+        let (value1, value2) = self.compare_registers(&instruction);
+        self.flags.condition = value1 != value2;
+      },
+      OpCode::GT => {
+        let (value1, value2) = self.compare_registers(&instruction);
+        self.flags.condition = value1 > value2;
+      },
+      OpCode::LT => {
+        let (value1, value2) = self.compare_registers(&instruction);
+        self.flags.condition = value1 < value2;
+      },
+      OpCode::GTE => {
+        let (value1, value2) = self.compare_registers(&instruction);
+        self.flags.condition = value1 >= value2;
+      },
+      OpCode::LTE => {
+        let (value1, value2) = self.compare_registers(&instruction);
+        self.flags.condition = value1 <= value2;
+      },
+      OpCode::JEQ => {
+        let condition = self.flags.condition;
+        self.jump_if(&instruction, condition)?;
+      },
+      OpCode::JNEQ => {
+        let condition = !self.flags.condition;
+        self.jump_if(&instruction, condition)?;
+      },
+      OpCode::ALOC => {
+        let bytes = self.register_value(&instruction, 0);
+        let destination = instruction.register(1);
+        if bytes < 0 {
+          return Err(VmErrorKind::HeapFault {
+            address: self.heap_address(self.heap.len()),
+            size: i64::from(bytes)
+          });
+        }
+        let start = self.heap.allocate(bytes as usize)?;
+        match i32::try_from(self.heap_address(start)) {
+          Ok(address) => self.registers[destination] = address,
+          Err(_) => {
+            // a block no register can address is no use to the program, give it back
+            self.heap.free(start)?;
+            return Err(VmErrorKind::OutOfMemory { requested: i64::from(bytes) });
+          }
+        }
+      },
+      OpCode::FREE => {
+        let address = i64::from(self.register_value(&instruction, 0));
+        let start = address - self.ro_data.len() as i64;
+        if start < 0 || self.heap.free(start as usize).is_err() {
+          return Err(VmErrorKind::InvalidFree { address });
+        }
+      },
+      OpCode::INC => {
+        let register = instruction.register(0);
+        self.registers[register] = self.add(self.registers[register], 1)?;
+      },
+      OpCode::DEC => {
+        let register = instruction.register(0);
+        self.registers[register] = self.sub(self.registers[register], 1)?;
+      },
+      OpCode::JC => {
+        let condition = self.flags.carry;
+        self.jump_if(&instruction, condition)?;
+      },
+      OpCode::JO => {
+        let condition = self.flags.overflow;
+        self.jump_if(&instruction, condition)?;
+      },
+      OpCode::REM => {
+        let register = instruction.register(0);
+        self.registers[register] = self.remainder;
+      },
+      OpCode::CMP => {
+        let (value1, value2) = self.compare_registers(&instruction);
+        self.flags.condition = value1 == value2;
+      },
+      OpCode::JZ => {
+        let condition = self.flags.zero;
+        self.jump_if(&instruction, condition)?;
+      },
+      OpCode::JNZ => {
+        let condition = !self.flags.zero;
+        self.jump_if(&instruction, condition)?;
+      },
+      OpCode::JS => {
+        let condition = self.flags.negative;
+        self.jump_if(&instruction, condition)?;
+      },
+      OpCode::JNS => {
+        let condition = !self.flags.negative;
+        self.jump_if(&instruction, condition)?;
+      },
+      OpCode::JNC => {
+        let condition = !self.flags.carry;
+        self.jump_if(&instruction, condition)?;
+      },
+      OpCode::JNO => {
+        let condition = !self.flags.overflow;
+        self.jump_if(&instruction, condition)?;
+      },
+      OpCode::JLT => {
+        let condition = self.flags.less();
+        self.jump_if(&instruction, condition)?;
+      },
+      OpCode::JGE => {
+        let condition = !self.flags.less();
+        self.jump_if(&instruction, condition)?;
+      },
+      OpCode::JGT => {
+        let condition = self.flags.greater();
+        self.jump_if(&instruction, condition)?;
+      },
+      OpCode::JLE => {
+        let condition = !self.flags.greater();
+        self.jump_if(&instruction, condition)?;
+      },
+      OpCode::AND => {
+        let (value1, value2, destination) = self.bitwise_operands(&instruction);
+        self.set_bitwise_result(destination, value1 & value2);
+      },
+      OpCode::OR => {
+        let (value1, value2, destination) = self.bitwise_operands(&instruction);
+        self.set_bitwise_result(destination, value1 | value2);
+      },
+      OpCode::XOR => {
+        let (value1, value2, destination) = self.bitwise_operands(&instruction);
+        self.set_bitwise_result(destination, value1 ^ value2);
+      },
+      OpCode::NOT => {
+        let value = self.register_value(&instruction, 0);
+        let destination = instruction.register(1);
+        self.set_bitwise_result(destination, !value);
+      },
+      // shift and rotate amounts only use their low 5 bits
+      OpCode::SHL => {
+        let (value, amount, destination) = self.bitwise_operands(&instruction);
+        self.set_bitwise_result(destination, value.wrapping_shl(amount as u32));
+      },
+      OpCode::SHR => {
+        let (value, amount, destination) = self.bitwise_operands(&instruction);
+        self.set_bitwise_result(destination, (value as u32).wrapping_shr(amount as u32) as i32);
+      },
+      OpCode::SAR => {
+        let (value, amount, destination) = self.bitwise_operands(&instruction);
+        self.set_bitwise_result(destination, value.wrapping_shr(amount as u32));
+      },
+      OpCode::ROL => {
+        let (value, amount, destination) = self.bitwise_operands(&instruction);
+        self.set_bitwise_result(destination, value.rotate_left(amount as u32 & 31));
+      },
+      OpCode::ROR => {
+        let (value, amount, destination) = self.bitwise_operands(&instruction);
+        self.set_bitwise_result(destination, value.rotate_right(amount as u32 & 31));
+      },
+      OpCode::LUI => {
+        // replaces the upper half of the register, keeping the half a LOAD just wrote
+        let register = instruction.register(0);
+        let upper = u32::from(instruction.integer(1));
+        let lower = self.registers[register] as u32 & 0xFFFF;
+        self.registers[register] = ((upper << 16) | lower) as i32;
+      },
+      OpCode::LB => {
+        let (register, address) = self.memory_operands(&instruction);
+        self.registers[register] = i32::from(self.read_memory(address, 1)? as u8 as i8);
+      },
+      OpCode::LBU => {
+        let (register, address) = self.memory_operands(&instruction);
+        self.registers[register] = self.read_memory(address, 1)? as i32;
+      },
+      OpCode::LH => {
+        let (register, address) = self.memory_operands(&instruction);
+        self.registers[register] = i32::from(self.read_memory(address, 2)? as u16 as i16);
+      },
+      OpCode::LHU => {
+        let (register, address) = self.memory_operands(&instruction);
+        self.registers[register] = self.read_memory(address, 2)? as i32;
+      },
+      OpCode::LW => {
+        let (register, address) = self.memory_operands(&instruction);
+        self.registers[register] = self.read_memory(address, 4)? as i32;
+      },
+      OpCode::SB => {
+        let (register, address) = self.memory_operands(&instruction);
+        self.write_memory(address, 1, self.registers[register] as u32)?;
+      },
+      OpCode::SH => {
+        let (register, address) = self.memory_operands(&instruction);
+        self.write_memory(address, 2, self.registers[register] as u32)?;
+      },
+      OpCode::SW => {
+        let (register, address) = self.memory_operands(&instruction);
+        self.write_memory(address, 4, self.registers[register] as u32)?;
+      },
+      OpCode::CALL => {
+        let target = instruction.integer(0);
+        // the return address is the instruction after the CALL
+        let return_address = self.pc as i32;
+        self.stack.push_frame(return_address)?;
+        self.jump_to(i64::from(target))?;
+      },
+      OpCode::RET => {
+        let target = self.stack.pop()?;
+        self.jump_to(i64::from(target))?;
+      },
+      OpCode::PUSH => {
+        let value = self.register_value(&instruction, 0);
+        self.stack.push(value)?;
+      },
+      OpCode::POP => {
+        let register = instruction.register(0);
+        self.registers[register] = self.stack.pop()?;
+      },
+      OpCode::SYSCALL => {
+        let number = instruction.integer(0);
+        // the function gets the whole VM, so it is moved out of the registry while it runs,
+        // functions it registers in the meantime are merged back afterwards
+        let mut functions = core::mem::take(&mut self.host_functions);
+        let result = match functions.get_mut(number) {
+          Some(function) => function(self),
+          None => Err(VmErrorKind::UnknownHostFunction { number })
+        };
+        let registered = core::mem::replace(&mut self.host_functions, functions);
+        self.host_functions.merge(registered);
+        result?;
+      },
+      OpCode::PRTI => {
+        let value = self.register_value(&instruction, 0);
+        self.print(value.to_string().as_bytes())?;
+      },
+      OpCode::PRTC => {
+        // the low byte, so strings can be printed a byte at a time
+        let value = self.register_value(&instruction, 0);
+        self.print(&[value as u8])?;
+      },
+      OpCode::PRTS => {
+        let address = i64::from(self.register_value(&instruction, 0));
+        let text = self.read_string(address)?;
+        self.print(&text)?;
+      },
+      OpCode::READI => {
+        let register = instruction.register(0);
+        let line = self.read_line()?;
+        self.registers[register] = line.trim().parse().map_err(|_| {
+          VmErrorKind::BadInput { input: line.clone() }
+        })?;
+      },
+      OpCode::READS => {
+        // copies the line to a new heap block, NUL terminated like `.asciiz` strings
+        let register = instruction.register(0);
+        let line = self.read_line()?;
+        let address = self.allocate(line.len() + 1)?;
+        self.write_bytes(address, line.as_bytes())?;
+        self.registers[register] = address as i32;
+      },
+      OpCode::IGL => {
+        return Err(VmErrorKind::IllegalOpcode);
+      }
+    }
+    Ok(ExitReason::Running)
+  }
+
+  fn add(&mut self, value1: i32, value2: i32) -> Result<i32, VmErrorKind> {
+    let (wrapped, overflow) = value1.overflowing_add(value2);
+    let (_, carry) = (value1 as u32).overflowing_add(value2 as u32);
+    self.apply_overflow(wrapped, overflow, carry, value1.saturating_add(value2))
+  }
+
+  fn sub(&mut self, value1: i32, value2: i32) -> Result<i32, VmErrorKind> {
+    let (wrapped, overflow) = value1.overflowing_sub(value2);
+    let (_, borrow) = (value1 as u32).overflowing_sub(value2 as u32);
+    self.apply_overflow(wrapped, overflow, borrow, value1.saturating_sub(value2))
+  }
+
+  /// Picks the result the overflow mode asks for and updates the flags from it
+  fn apply_overflow(&mut self, wrapped: i32, overflow: bool, carry: bool, saturated: i32) -> Result<i32, VmErrorKind> {
+    if overflow && self.overflow_mode == OverflowMode::Trap {
+      return Err(VmErrorKind::ArithmeticOverflow);
+    }
+    let result = match self.overflow_mode {
+      OverflowMode::Saturating => saturated,
+      _ => wrapped
+    };
+    self.flags.set_result(result, carry, overflow);
+    Ok(result)
+  }
+
+  /// The value held by the register operand `index` of `instruction` names
+  fn register_value(&self, instruction: &Instruction, index: usize) -> i32 {
+    self.registers[instruction.register(index)]
+  }
+
+  /// The two source register values and the destination register of a bitwise instruction
+  fn bitwise_operands(&self, instruction: &Instruction) -> (i32, i32, usize) {
+    (self.register_value(instruction, 0), self.register_value(instruction, 1), instruction.register(2))
+  }
+
+  /// Bitwise results never carry or overflow, only zero and negative are meaningful
+  fn set_bitwise_result(&mut self, destination: usize, result: i32) {
+    self.registers[destination] = result;
+    self.flags.set_result(result, false, false);
+  }
+
+  /// Compares the two registers of a comparison and sets the flags
+  fn compare_registers(&mut self, instruction: &Instruction) -> (i32, i32) {
+    let value1 = self.register_value(instruction, 0);
+    let value2 = self.register_value(instruction, 1);
+    self.flags.compare(value1, value2);
+    (value1, value2)
+  }
+
+  /// The value register and the address, base register plus signed offset, of a load or store
+  fn memory_operands(&self, instruction: &Instruction) -> (usize, i64) {
+    let base = self.register_value(instruction, 1);
+    let offset = instruction.integer(2) as i16;
+    (instruction.register(0), i64::from(base) + i64::from(offset))
+  }
+
+  /// Address of the heap byte at `offset`, the heap comes after the data section
+  fn heap_address(&self, offset: usize) -> i64 {
+    (self.ro_data.len() + offset) as i64
+  }
+
+  /// Reads the big endian value of `size` bytes at `address`
+  fn read_memory(&self, address: i64, size: usize) -> Result<u32, VmErrorKind> {
+    let bytes = self.read_bytes(address, size)?;
+    Ok(bytes.iter().fold(0, |value, byte| (value << 8) | u32::from(*byte)))
+  }
+
+  /// Writes the low `size` bytes of `value` at `address`, big endian
+  fn write_memory(&mut self, address: i64, size: usize, value: u32) -> Result<(), VmErrorKind> {
+    self.write_bytes(address, &value.to_be_bytes()[4 - size..])
+  }
+
+  #[cfg(feature = "std")]
+  fn print(&mut self, bytes: &[u8]) -> Result<(), VmErrorKind> {
+    let output = &mut self.console.output;
+    output
+      .write_all(bytes)
+      .and_then(|_| output.flush())
+      .map_err(|error| VmErrorKind::Io { message: error.to_string() })
+  }
+
+  /// Reads a line of input without its line ending
+  #[cfg(feature = "std")]
+  fn read_line(&mut self) -> Result<String, VmErrorKind> {
+    let mut line = String::new();
+    let read = self.console.input
+      .read_line(&mut line)
+      .map_err(|error| VmErrorKind::Io { message: error.to_string() })?;
+    if read == 0 {
+      return Err(VmErrorKind::EndOfInput);
+    }
+    let len = line.trim_end_matches(['\n', '\r']).len();
+    line.truncate(len);
+    Ok(line)
+  }
+
+  /// Without std there is no console, the I/O instructions fail
+  #[cfg(not(feature = "std"))]
+  fn print(&mut self, _bytes: &[u8]) -> Result<(), VmErrorKind> {
+    Err(VmErrorKind::Io { message: "no console without the std feature".to_string() })
+  }
+
+  #[cfg(not(feature = "std"))]
+  fn read_line(&mut self) -> Result<String, VmErrorKind> {
+    Err(VmErrorKind::Io { message: "no console without the std feature".to_string() })
+  }
+
+  /// The bytes from `address` up to the next NUL
+  fn read_string(&self, address: i64) -> Result<Vec<u8>, VmErrorKind> {
+    let mut text = vec![];
+    loop {
+      match self.read_bytes(address + text.len() as i64, 1)?[0] {
+        0 => return Ok(text),
+        byte => text.push(byte)
+      }
+    }
+  }
+
+  /// Jumps to the target in the register operand of `instruction` when `condition` holds
+  fn jump_if(&mut self, instruction: &Instruction, condition: bool) -> Result<(), VmErrorKind> {
+    let target = self.register_value(instruction, 0);
+    if condition {
+      self.jump_to(i64::from(target))?;
+    }
+    Ok(())
+  }
+
+  /// Moves the program counter, a target equal to the program length ends the program
+  fn jump_to(&mut self, target: i64) -> Result<(), VmErrorKind> {
+    if target < 0 || target > self.program.len() as i64 {
+      return Err(VmErrorKind::BadJumpTarget { target });
+    }
+    self.pc = target as usize;
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn get_vm() -> VM {
+    VM::new()
+  }
+
+  #[test]
+  fn create_vm() {
+    let test_vm = get_vm();
+    assert_eq!(0, test_vm.registers[0]);
+    assert_eq!(0, test_vm.pc);
+  }
+
+  #[test]
+  fn test_opcode_hlt() {
+    let mut test_vm = get_vm();
+    let test_code = vec![OpCode::HLT as u8, 0, 0, 0];
+    test_vm.program = test_code;
+    assert_eq!(ExitReason::Halted { status: 0 }, test_vm.run_once().unwrap());
+    assert_eq!(1, test_vm.pc);
+  }
+
+  #[test]
+  fn test_opcode_ilg() {
+    let mut test_vm = get_vm();
+    let test_code = vec![254, 0, 0, 0];
+    test_vm.program = test_code;
+    let error = test_vm.run_once().unwrap_err();
+    assert_eq!(1, test_vm.pc);
+    assert_eq!(VmError::new(0, 254, VmErrorKind::IllegalOpcode), error);
+  }
+
+  #[test]
+  fn test_load_opcode() {
+    let mut test_vm = get_vm();
+    // last 2 operands are 1000 as 2u8 in hex format (3 * 16 * 16 and 14 * 16 + 8)
+    let test_code = vec![OpCode::LOAD as u8, 0, 3, 232];
+    test_vm.program = test_code;
+    test_vm.run_once().unwrap();
+    assert_eq!(1000, test_vm.registers[0]);
+  }
+
+  #[test]
+  fn test_add_opcode() {
+    let mut test_vm = get_vm();
+    test_vm.registers[0] = 10;
+    test_vm.registers[1] = 15;
+    let test_code = vec![OpCode::ADD as u8, 0, 1, 2];
+    test_vm.program = test_code;
+    test_vm.run_once().unwrap();
+    assert_eq!(25, test_vm.registers[2]);
+  }
+
+  #[test]
+  fn test_sub_opcode() {
+    let mut test_vm = get_vm();
+    test_vm.registers[0] = 10;
+    test_vm.registers[1] = 15;
+    let test_code = vec![OpCode::SUB as u8, 0, 1, 2];
+    test_vm.program = test_code;
+    test_vm.run_once().unwrap();
+    assert_eq!(-5, test_vm.registers[2]);
+  }
+
+  #[test]
+  fn test_mul_opcode() {
+    let mut test_vm = get_vm();
+    test_vm.registers[0] = 10;
+    test_vm.registers[1] = 15;
+    let test_code = vec![OpCode::MUL as u8, 0, 1, 2];
+    test_vm.program = test_code;
+    test_vm.run_once().unwrap();
+    assert_eq!(150, test_vm.registers[2]);
+  }
+
+  #[test]
+  fn test_div_opcode() {
+    let mut test_vm = get_vm();
+    test_vm.registers[0] = 17;
+    test_vm.registers[1] = 4;
+    let test_code = vec![OpCode::DIV as u8, 0, 1, 2];
+    test_vm.program = test_code;
+    test_vm.run_once().unwrap();
+    assert_eq!(4, test_vm.registers[2]);
+    assert_eq!(1, test_vm.remainder);
+  }
+
+  #[test]
+  fn test_jmp_opcode() {
+    let mut test_vm = get_vm();
+    test_vm.registers[0] = 10;
+    let mut test_code = vec![OpCode::JMP as u8, 0, 0, 0];
+    // jump targets must lie within the program
+    test_code.resize(12, 0);
+    test_vm.program = test_code;
+    test_vm.run_once().unwrap();
+    assert_eq!(10, test_vm.pc);
+  }
+
+  #[test]
+  fn test_jmpf_opcode() {
+    let mut test_vm = get_vm();
+    test_vm.registers[0] = 2;
+    let test_code = vec![OpCode::JMPF as u8, 0, 0, 0, OpCode::HLT as u8, 0, 0, 0];
+    test_vm.program = test_code;
+    test_vm.run_once().unwrap();
+    assert_eq!(4, test_vm.pc);
+  }
+
+  #[test]
+  fn test_jmpb_opcode() {
+    let mut test_vm = get_vm();
+    test_vm.registers[0] = 2;
+    let test_code = vec![OpCode::JMPB as u8, 0, 0, 0];
+    test_vm.program = test_code;
+    test_vm.run_once().unwrap();
+    assert_eq!(0, test_vm.pc);
+  }
+
+  #[test]
+  fn test_eq_opcode() {
+    let mut test_vm = get_vm();
+    test_vm.registers[0] = 2;
+    test_vm.registers[1] = 2;
+    test_vm.registers[2] = 2;
+    test_vm.registers[3] = 3;
+    let test_code = vec![OpCode::EQ as u8, 0, 1, 0, OpCode::EQ as u8, 2, 3, 0];
+    test_vm.program = test_code;
+    test_vm.run_once().unwrap();
+    assert!(test_vm.flags.condition);
+    test_vm.run_once().unwrap();
+    assert!(!test_vm.flags.condition);
+  }
+
+  #[test]
+  fn test_neq_opcode() {
+    let mut test_vm = get_vm();
+    test_vm.registers[0] = 0;
+    test_vm.registers[1] = 3;
+    test_vm.registers[2] = 0;
+    test_vm.registers[3] = 0;
+    let test_code = vec![OpCode::NEQ as u8, 0, 1, 0, OpCode::NEQ as u8, 2, 3, 0];
+    test_vm.program = test_code;
+    test_vm.run_once().unwrap();
+    assert!(test_vm.flags.condition);
+    test_vm.run_once().unwrap();
+    assert!(!test_vm.flags.condition);
+  }
+
+  #[test]
+  fn test_gt_opcode() {
+    let code = OpCode::GT as u8;
+    let mut test_vm = get_vm();
+    test_vm.registers[0] = 3;
+    test_vm.registers[1] = 0;
+    test_vm.registers[2] = 0;
+    test_vm.registers[3] = 2;
+    let test_code = vec![code, 0, 1, 0, code, 2, 3, 0];
+    test_vm.program = test_code;
+    test_vm.run_once().unwrap();
+    assert!(test_vm.flags.condition);
+    test_vm.run_once().unwrap();
+    assert!(!test_vm.flags.condition);
+  }
+
+  #[test]
+  fn test_lt_opcode() {
+    let code = OpCode::LT as u8;
+    let mut test_vm = get_vm();
+    test_vm.registers[0] = 0;
+    test_vm.registers[1] = 3;
+    test_vm.registers[2] = 2;
+    test_vm.registers[3] = 0;
+    let test_code = vec![code, 0, 1, 0, code, 2, 3, 0];
+    test_vm.program = test_code;
+    test_vm.run_once().unwrap();
+    assert!(test_vm.flags.condition);
+    test_vm.run_once().unwrap();
+    assert!(!test_vm.flags.condition);
+  }
+
+  #[test]
+  fn test_gte_opcode() {
+    let code = OpCode::GTE as u8;
+    let mut test_vm = get_vm();
+    test_vm.registers[0] = 3;
+    test_vm.registers[1] = 0;
+    test_vm.registers[2] = 0;
+    test_vm.registers[3] = 3;
+    let test_code = vec![code, 0, 1, 0, code, 0, 1, 0, code, 2, 3, 0];
+    test_vm.program = test_code;
+    test_vm.run_once().unwrap();
+    assert!(test_vm.flags.condition);
+    test_vm.registers[1] = 3;
+    test_vm.run_once().unwrap();
+    assert!(test_vm.flags.condition);
+    test_vm.run_once().unwrap();
+    assert!(!test_vm.flags.condition);
+  }
+
+  #[test]
+  fn test_lte_opcode() {
+    let code = OpCode::LTE as u8;
+    let mut test_vm = get_vm();
+    test_vm.registers[0] = 0;
+    test_vm.registers[1] = 3;
+    test_vm.registers[2] = 3;
+    test_vm.registers[3] = 0;
+    let test_code = vec![code, 0, 1, 0, code, 0, 1, 0, code, 2, 3, 0];
+    test_vm.program = test_code;
+    test_vm.run_once().unwrap();
+    assert!(test_vm.flags.condition);
+    test_vm.registers[0] = 3;
+    test_vm.run_once().unwrap();
+    assert!(test_vm.flags.condition);
+    test_vm.run_once().unwrap();
+    assert!(!test_vm.flags.condition);
+  }
+
+  #[test]
+  fn test_jeq_opcode() {
+    let mut test_vm = get_vm();
+    test_vm.registers[0] = 10;
+    test_vm.flags.condition = true;
+    let mut test_code = vec![OpCode::JEQ as u8, 0, 0, 0];
+    // jump targets must lie within the program
+    test_code.resize(12, 0);
+    test_vm.program = test_code;
+    test_vm.run_once().unwrap();
+    assert_eq!(10, test_vm.pc);
+  }
+
+  #[test]
+  fn test_jneq_opcode() {
+    let mut test_vm = get_vm();
+    test_vm.registers[0] = 10;
+    test_vm.flags.condition = false;
+    let mut test_code = vec![OpCode::JNEQ as u8, 0, 0, 0];
+    // jump targets must lie within the program
+    test_code.resize(12, 0);
+    test_vm.program = test_code;
+    test_vm.run_once().unwrap();
+    assert_eq!(10, test_vm.pc);
+  }
+
+  #[test]
+  fn test_aloc_opcode() {
+      let mut test_vm = get_vm();
+      // initialize heap to 512 length
+      test_vm.heap.allocate(512).unwrap();
+      test_vm.registers[0] = 512;
+      // opcode, register with the size, register receiving the address
+      let test_code = vec![OpCode::ALOC as u8, 0, 1];
+      test_vm.program = test_code;
+      test_vm.run_once().unwrap();
+      assert_eq!(1024, test_vm.heap.len());
+      assert_eq!(512, test_vm.registers[1]);
+  }
+
+  #[test]
+  fn test_free_opcode_reuses_block() {
+    let mut test_vm = get_vm();
+    test_vm.ro_data = vec![0; 4];
+    test_vm.registers[0] = 8;
+    test_vm.program = vec![
+      OpCode::ALOC as u8, 0, 1,
+      OpCode::ALOC as u8, 0, 2,
+      OpCode::FREE as u8, 1,
+      OpCode::ALOC as u8, 0, 3,
+    ];
+    assert_eq!(Ok(ExitReason::EndOfProgram), test_vm.run());
+    assert_eq!([4, 12, 4], test_vm.registers[1..4]);
+    assert_eq!(16, test_vm.heap.len());
+  }
+
+  #[test]
+  fn test_invalid_free() {
+    for &address in [0, 5, 100, -1].iter() {
+      let mut test_vm = get_vm();
+      test_vm.ro_data = vec![0; 4];
+      test_vm.heap.allocate(8).unwrap();
+      test_vm.registers[0] = address;
+      test_vm.program = vec![OpCode::FREE as u8, 0];
+      let error = test_vm.run_once().unwrap_err();
+      assert_eq!(VmErrorKind::InvalidFree { address: i64::from(address) }, error.kind);
+    }
+  }
+
+  #[test]
+  fn test_aloc_out_of_memory() {
+    let mut test_vm = VM::new().with_max_heap_size(16);
+    test_vm.registers[0] = 10;
+    test_vm.program = vec![OpCode::ALOC as u8, 0, 1, OpCode::ALOC as u8, 0, 1];
+    test_vm.run_once().unwrap();
+    let error = test_vm.run_once().unwrap_err();
+    assert_eq!(VmError::new(3, OpCode::ALOC as u8, VmErrorKind::OutOfMemory { requested: 10 }), error);
+    assert_eq!(10, test_vm.heap.len());
+  }
+
+  #[test]
+  fn test_aloc_address_past_registers() {
+    let mut test_vm = VM::new().with_max_heap_size(usize::MAX);
+    // zeroed, so the pages are not touched
+    test_vm.ro_data = vec![0; i32::MAX as usize];
+    test_vm.registers[0] = 1;
+    test_vm.program = vec![OpCode::ALOC as u8, 0, 1, OpCode::ALOC as u8, 0, 2];
+    test_vm.run_once().unwrap();
+    assert_eq!(i32::MAX, test_vm.registers[1]);
+    assert_eq!(VmErrorKind::OutOfMemory { requested: 1 }, test_vm.run_once().unwrap_err().kind);
+    assert_eq!(0, test_vm.registers[2]);
+  }
+
+  #[test]
+  fn test_inc_opcode() {
+      let mut test_vm = get_vm();
+      test_vm.registers[0] = 10;
+      let test_code = vec![OpCode::INC as u8, 0, 0, 0];
+      test_vm.program = test_code;
+      test_vm.run_once().unwrap();
+      assert_eq!(11, test_vm.registers[0]);
+  }
+
+  #[test]
+  fn test_dec_opcode() {
+      let mut test_vm = get_vm();
+      test_vm.registers[0] = 10;
+      let test_code = vec![OpCode::DEC as u8, 0, 0, 0];
+      test_vm.program = test_code;
+      test_vm.run_once().unwrap();
+      assert_eq!(9, test_vm.registers[0]);
+  }
+
+  #[test]
+  fn test_run_until_end_of_program() {
+    let mut test_vm = get_vm();
+    test_vm.program = vec![OpCode::INC as u8, 0, OpCode::INC as u8, 0];
+    assert_eq!(Ok(ExitReason::EndOfProgram), test_vm.run());
+    assert_eq!(2, test_vm.registers[0]);
+  }
+
+  #[test]
+  fn test_run_until_hlt() {
+    let mut test_vm = get_vm();
+    test_vm.program = vec![OpCode::INC as u8, 0, OpCode::HLT as u8, OpCode::INC as u8, 0];
+    assert_eq!(Ok(ExitReason::Halted { status: 0 }), test_vm.run());
+    assert_eq!(1, test_vm.registers[0]);
+  }
+
+  #[test]
+  fn test_register_out_of_range() {
+    let mut test_vm = get_vm();
+    test_vm.program = vec![OpCode::HLT as u8, OpCode::INC as u8, 32];
+    test_vm.pc = 1;
+    let error = test_vm.run_once().unwrap_err();
+    assert_eq!(
+      VmError::new(1, OpCode::INC as u8, VmErrorKind::RegisterOutOfRange { register: 32 }),
+      error
+    );
+  }
+
+  #[test]
+  fn test_truncated_instruction() {
+    let mut test_vm = get_vm();
+    test_vm.program = vec![OpCode::LOAD as u8, 0, 3];
+    let error = test_vm.run().unwrap_err();
+    assert_eq!(VmErrorKind::TruncatedInstruction, error.kind);
+    assert_eq!(0, error.pc);
+  }
+
+  #[test]
+  fn test_bad_jump_target() {
+    let mut test_vm = get_vm();
+    test_vm.registers[0] = 5;
+    test_vm.program = vec![OpCode::JMPB as u8, 0];
+    let error = test_vm.run_once().unwrap_err();
+    assert_eq!(VmErrorKind::BadJumpTarget { target: -3 }, error.kind);
+
+    let mut test_vm = get_vm();
+    test_vm.registers[0] = 100;
+    test_vm.program = vec![OpCode::JMP as u8, 0];
+    let error = test_vm.run_once().unwrap_err();
+    assert_eq!(VmErrorKind::BadJumpTarget { target: 100 }, error.kind);
+  }
+
+  #[test]
+  fn test_aloc_negative_size() {
+    let mut test_vm = get_vm();
+    test_vm.registers[0] = -1;
+    test_vm.program = vec![OpCode::ALOC as u8, 0, 1];
+    let error = test_vm.run_once().unwrap_err();
+    assert_eq!(VmErrorKind::HeapFault { address: 0, size: -1 }, error.kind);
+    assert!(test_vm.heap.is_empty());
+  }
+
+  #[test]
+  fn test_add_overflow_wrapping() {
+    let mut test_vm = get_vm();
+    test_vm.registers[0] = i32::MAX;
+    test_vm.registers[1] = 1;
+    test_vm.program = vec![OpCode::ADD as u8, 0, 1, 2];
+    test_vm.run_once().unwrap();
+    assert_eq!(i32::MIN, test_vm.registers[2]);
+    assert!(test_vm.flags.overflow);
+    assert!(!test_vm.flags.carry);
+  }
+
+  #[test]
+  fn test_add_overflow_saturating() {
+    let mut test_vm = VM::new().with_overflow_mode(OverflowMode::Saturating);
+    test_vm.registers[0] = i32::MIN;
+    test_vm.registers[1] = -1;
+    test_vm.program = vec![OpCode::ADD as u8, 0, 1, 2];
+    test_vm.run_once().unwrap();
+    assert_eq!(i32::MIN, test_vm.registers[2]);
+    assert!(test_vm.flags.overflow);
+    assert!(test_vm.flags.carry);
+  }
+
+  #[test]
+  fn test_mul_overflow_trap() {
+    let mut test_vm = VM::new().with_overflow_mode(OverflowMode::Trap);
+    test_vm.registers[0] = 1 << 20;
+    test_vm.registers[1] = 1 << 20;
+    test_vm.program = vec![OpCode::HLT as u8, OpCode::MUL as u8, 0, 1, 2];
+    test_vm.pc = 1;
+    let error = test_vm.run_once().unwrap_err();
+    assert_eq!(VmError::new(1, OpCode::MUL as u8, VmErrorKind::ArithmeticOverflow), error);
+    assert_eq!(0, test_vm.registers[2]);
+  }
+
+  #[test]
+  fn test_div_overflow() {
+    let mut test_vm = VM::new().with_overflow_mode(OverflowMode::Saturating);
+    test_vm.registers[0] = i32::MIN;
+    test_vm.registers[1] = -1;
+    test_vm.program = vec![OpCode::DIV as u8, 0, 1, 2];
+    test_vm.run_once().unwrap();
+    assert_eq!(i32::MAX, test_vm.registers[2]);
+    assert_eq!(0, test_vm.remainder);
+    assert!(test_vm.flags.overflow);
+  }
+
+  #[test]
+  fn test_inc_dec_overflow() {
+    let mut test_vm = VM::new().with_overflow_mode(OverflowMode::Trap);
+    test_vm.registers[0] = i32::MAX;
+    test_vm.registers[1] = i32::MIN;
+    test_vm.program = vec![OpCode::INC as u8, 0, OpCode::DEC as u8, 1];
+    assert_eq!(VmErrorKind::ArithmeticOverflow, test_vm.run_once().unwrap_err().kind);
+
+    let mut test_vm = get_vm();
+    test_vm.registers[1] = i32::MIN;
+    test_vm.program = vec![OpCode::DEC as u8, 1];
+    test_vm.run_once().unwrap();
+    assert_eq!(i32::MAX, test_vm.registers[1]);
+    assert!(test_vm.flags.overflow);
+  }
+
+  #[test]
+  fn test_sub_borrow_sets_carry() {
+    let mut test_vm = get_vm();
+    test_vm.registers[0] = 1;
+    test_vm.registers[1] = 2;
+    test_vm.program = vec![OpCode::SUB as u8, 0, 1, 2, OpCode::SUB as u8, 1, 0, 2];
+    test_vm.run_once().unwrap();
+    assert_eq!(-1, test_vm.registers[2]);
+    assert!(test_vm.flags.carry);
+    assert!(!test_vm.flags.overflow);
+    test_vm.run_once().unwrap();
+    assert!(!test_vm.flags.carry);
+  }
+
+  #[test]
+  fn test_jc_jo_opcodes() {
+    let mut test_vm = get_vm();
+    test_vm.registers[0] = 10;
+    test_vm.flags.carry = true;
+    let mut test_code = vec![OpCode::JC as u8, 0, OpCode::JO as u8, 0];
+    test_code.resize(12, 0);
+    test_vm.program = test_code;
+    test_vm.run_once().unwrap();
+    assert_eq!(10, test_vm.pc);
+
+    test_vm.pc = 2;
+    test_vm.run_once().unwrap();
+    assert_eq!(4, test_vm.pc);
+    test_vm.flags.overflow = true;
+    test_vm.pc = 2;
+    test_vm.run_once().unwrap();
+    assert_eq!(10, test_vm.pc);
+  }
+
+  #[test]
+  fn test_div_by_zero() {
+    let mut test_vm = get_vm();
+    test_vm.registers[0] = 17;
+    test_vm.registers[2] = 42;
+    test_vm.program = vec![OpCode::DIV as u8, 0, 1, 2];
+    let error = test_vm.run_once().unwrap_err();
+    assert_eq!(VmError::new(0, OpCode::DIV as u8, VmErrorKind::DivisionByZero), error);
+    assert_eq!(42, test_vm.registers[2]);
+  }
+
+  #[test]
+  fn test_div_negative_remainder() {
+    let mut test_vm = get_vm();
+    test_vm.registers[0] = -17;
+    test_vm.registers[1] = 4;
+    test_vm.program = vec![OpCode::DIV as u8, 0, 1, 2, OpCode::REM as u8, 3];
+    test_vm.run().unwrap();
+    assert_eq!(-4, test_vm.registers[2]);
+    assert_eq!(-1, test_vm.registers[3]);
+  }
+
+  #[test]
+  fn test_rem_opcode() {
+    let mut test_vm = get_vm();
+    test_vm.remainder = 3;
+    test_vm.program = vec![OpCode::REM as u8, 5];
+    test_vm.run_once().unwrap();
+    assert_eq!(3, test_vm.registers[5]);
+  }
+
+  #[test]
+  fn test_cmp_opcode() {
+    let mut test_vm = get_vm();
+    test_vm.registers[0] = -5;
+    test_vm.registers[1] = 3;
+    test_vm.program = vec![OpCode::CMP as u8, 0, 1, 0, OpCode::CMP as u8, 1, 1, 0];
+    test_vm.run_once().unwrap();
+    assert!(test_vm.flags.less());
+    assert!(!test_vm.flags.zero);
+    assert!(!test_vm.flags.condition);
+    test_vm.run_once().unwrap();
+    assert!(test_vm.flags.zero);
+    assert!(test_vm.flags.condition);
+  }
+
+  #[test]
+  fn test_arithmetic_sets_zero_and_negative() {
+    let mut test_vm = get_vm();
+    test_vm.registers[0] = 1;
+    test_vm.program = vec![OpCode::DEC as u8, 0, OpCode::DEC as u8, 0];
+    test_vm.run_once().unwrap();
+    assert!(test_vm.flags.zero);
+    assert!(!test_vm.flags.negative);
+    test_vm.run_once().unwrap();
+    assert!(!test_vm.flags.zero);
+    assert!(test_vm.flags.negative);
+  }
+
+  #[test]
+  fn test_comparison_branches_both_ways() {
+    let mut test_vm = get_vm();
+    test_vm.registers[0] = 1;
+    test_vm.registers[1] = 2;
+    test_vm.registers[2] = 12;
+    test_vm.registers[3] = 14;
+    // lt $0 $1; jge $2; jlt $3; ...; 12: hlt; 13: hlt; 14: inc $4
+    let mut test_code = vec![
+      OpCode::LT as u8, 0, 1, 0,
+      OpCode::JGE as u8, 2,
+      OpCode::JLT as u8, 3
+    ];
+    test_code.resize(12, 0);
+    test_code.extend_from_slice(&[OpCode::HLT as u8, OpCode::HLT as u8, OpCode::INC as u8, 4]);
+    test_vm.program = test_code;
+    assert_eq!(Ok(ExitReason::EndOfProgram), test_vm.run());
+    assert_eq!(1, test_vm.registers[4]);
+  }
+
+  #[test]
+  fn test_flag_jumps() {
+    let cases = [
+      (OpCode::JZ, Flags { zero: true, ..Flags::default() }),
+      (OpCode::JNZ, Flags::default()),
+      (OpCode::JS, Flags { negative: true, ..Flags::default() }),
+      (OpCode::JNS, Flags::default()),
+      (OpCode::JNC, Flags::default()),
+      (OpCode::JNO, Flags::default()),
+      (OpCode::JLT, Flags { overflow: true, ..Flags::default() }),
+      (OpCode::JGE, Flags { negative: true, overflow: true, ..Flags::default() }),
+      (OpCode::JGT, Flags::default()),
+      (OpCode::JLE, Flags { zero: true, ..Flags::default() }),
+    ];
+    for &(opcode, flags) in cases.iter() {
+      let mut test_vm = get_vm();
+      test_vm.registers[0] = 10;
+      let mut test_code = vec![opcode as u8, 0];
+      test_code.resize(12, 0);
+      test_vm.program = test_code;
+      test_vm.flags = flags;
+      test_vm.run_once().unwrap();
+      assert_eq!(10, test_vm.pc, "{:?} should jump", opcode);
+    }
+  }
+
+  #[test]
+  fn test_flag_jumps_not_taken() {
+    let mut test_vm = get_vm();
+    test_vm.registers[0] = 10;
+    let mut test_code = vec![OpCode::JZ as u8, 0, OpCode::JGT as u8, 0];
+    test_code.resize(12, 0);
+    test_vm.program = test_code;
+    test_vm.flags.negative = true;
+    test_vm.run_once().unwrap();
+    assert_eq!(2, test_vm.pc);
+    test_vm.run_once().unwrap();
+    assert_eq!(4, test_vm.pc);
+  }
+
+  #[test]
+  fn test_and_opcode() {
+    let mut test_vm = get_vm();
+    test_vm.registers[0] = 0b1100;
+    test_vm.registers[1] = 0b1010;
+    let test_code = vec![OpCode::AND as u8, 0, 1, 2];
+    test_vm.program = test_code;
+    test_vm.run_once().unwrap();
+    assert_eq!(0b1000, test_vm.registers[2]);
+  }
+
+  #[test]
+  fn test_or_opcode() {
+    let mut test_vm = get_vm();
+    test_vm.registers[0] = 0b1100;
+    test_vm.registers[1] = 0b1010;
+    let test_code = vec![OpCode::OR as u8, 0, 1, 2];
+    test_vm.program = test_code;
+    test_vm.run_once().unwrap();
+    assert_eq!(0b1110, test_vm.registers[2]);
+  }
+
+  #[test]
+  fn test_xor_opcode() {
+    let mut test_vm = get_vm();
+    test_vm.registers[0] = 0b1100;
+    test_vm.registers[1] = 0b1010;
+    let test_code = vec![OpCode::XOR as u8, 0, 1, 2];
+    test_vm.program = test_code;
+    test_vm.run_once().unwrap();
+    assert_eq!(0b0110, test_vm.registers[2]);
+  }
+
+  #[test]
+  fn test_shl_opcode() {
+    let mut test_vm = get_vm();
+    test_vm.registers[0] = -3;
+    test_vm.registers[1] = 2;
+    let test_code = vec![OpCode::SHL as u8, 0, 1, 2];
+    test_vm.program = test_code;
+    test_vm.run_once().unwrap();
+    assert_eq!(-12, test_vm.registers[2]);
+  }
+
+  #[test]
+  fn test_shr_opcode() {
+    let mut test_vm = get_vm();
+    test_vm.registers[0] = -1;
+    test_vm.registers[1] = 28;
+    let test_code = vec![OpCode::SHR as u8, 0, 1, 2];
+    test_vm.program = test_code;
+    test_vm.run_once().unwrap();
+    assert_eq!(0xF, test_vm.registers[2]);
+  }
+
+  #[test]
+  fn test_sar_opcode() {
+    let mut test_vm = get_vm();
+    test_vm.registers[0] = -16;
+    test_vm.registers[1] = 2;
+    let test_code = vec![OpCode::SAR as u8, 0, 1, 2];
+    test_vm.program = test_code;
+    test_vm.run_once().unwrap();
+    assert_eq!(-4, test_vm.registers[2]);
+  }
+
+  #[test]
+  fn test_rol_opcode() {
+    let mut test_vm = get_vm();
+    test_vm.registers[0] = 0x4000_0001;
+    test_vm.registers[1] = 2;
+    let test_code = vec![OpCode::ROL as u8, 0, 1, 2];
+    test_vm.program = test_code;
+    test_vm.run_once().unwrap();
+    assert_eq!(5, test_vm.registers[2]);
+  }
+
+  #[test]
+  fn test_ror_opcode() {
+    let mut test_vm = get_vm();
+    test_vm.registers[0] = 5;
+    test_vm.registers[1] = 1;
+    let test_code = vec![OpCode::ROR as u8, 0, 1, 2];
+    test_vm.program = test_code;
+    test_vm.run_once().unwrap();
+    assert_eq!(0x8000_0002u32 as i32, test_vm.registers[2]);
+  }
+
+  #[test]
+  fn test_not_opcode() {
+    let mut test_vm = get_vm();
+    test_vm.registers[0] = 0;
+    let test_code = vec![OpCode::NOT as u8, 0, 1];
+    test_vm.program = test_code;
+    test_vm.run_once().unwrap();
+    assert_eq!(-1, test_vm.registers[1]);
+    assert!(test_vm.flags.negative);
+  }
+
+  #[test]
+  fn test_shift_amount_uses_low_bits() {
+    let mut test_vm = get_vm();
+    test_vm.registers[0] = 1;
+    test_vm.registers[1] = 33;
+    let test_code = vec![OpCode::SHL as u8, 0, 1, 2];
+    test_vm.program = test_code;
+    test_vm.run_once().unwrap();
+    assert_eq!(2, test_vm.registers[2]);
+  }
+
+  #[test]
+  fn test_lui_opcode() {
+    let mut test_vm = get_vm();
+    test_vm.registers[0] = 0x1234_5678;
+    let test_code = vec![OpCode::LUI as u8, 0, 0xFF, 0xFE];
+    test_vm.program = test_code;
+    test_vm.run_once().unwrap();
+    assert_eq!(0xFFFE_5678u32 as i32, test_vm.registers[0]);
+  }
+
+  #[test]
+  fn test_wide_load() {
+    let mut test_vm = get_vm();
+    let test_code = vec![OpCode::LOAD as u8, 0, 0x11, 0x70, OpCode::LUI as u8, 0, 0x00, 0x01];
+    test_vm.program = test_code;
+    test_vm.run().unwrap();
+    assert_eq!(70000, test_vm.registers[0]);
+  }
+
+  #[test]
+  fn test_opcode_sizes_match_table() {
+    use instruction::OPCODES;
+    for info in OPCODES.iter() {
+      // relative jumps and CALL land elsewhere, they have tests of their own
+      if info.opcode == OpCode::JMPF || info.opcode == OpCode::JMPB || info.opcode == OpCode::CALL {
+        continue;
+      }
+      #[cfg(feature = "std")]
+      let mut test_vm = VM::new().with_output(SharedBuffer::new()).with_input(&b"1\n"[..]);
+      // there is no console for the I/O instructions to use
+      #[cfg(not(feature = "std"))]
+      let mut test_vm = match info.opcode {
+        OpCode::PRTI | OpCode::PRTC | OpCode::PRTS | OpCode::READI | OpCode::READS => continue,
+        _ => VM::new()
+      };
+      // jumps taken land right after the instruction as well
+      test_vm.registers = [info.size as i32; REGISTER_COUNT];
+      // FREE releases the block at the address in the registers
+      test_vm.heap.allocate(info.size).unwrap();
+      test_vm.heap.allocate(16).unwrap();
+      // RET returns to the address on the stack
+      test_vm.stack.push(info.size as i32).unwrap();
+      test_vm.host_functions_mut().register(0, "nop", |_| Ok(()));
+      let mut test_code = vec![info.opcode as u8];
+      test_code.resize(info.size * 2, 0);
+      test_vm.program = test_code;
+      test_vm.run_once().unwrap();
+      assert_eq!(info.size, test_vm.pc, "{:?}", info.opcode);
+    }
+  }
+
+  #[test]
+  fn test_predecoded_program_falls_back_to_decoding() {
+    let mut test_vm = get_vm();
+    // offset 6, inside the second LOAD, holds `inc $2`
+    let code = vec![
+      OpCode::LOAD as u8, 0, 0, 6,
+      OpCode::LOAD as u8, 1, OpCode::INC as u8, 2,
+      OpCode::JMP as u8, 0,
+    ];
+    test_vm.load_program(&Bytecode::new(code, vec![]).to_bytes()).unwrap();
+    assert_eq!(3, test_vm.decoded.len());
+    for _ in 0..4 {
+      test_vm.run_once().unwrap();
+    }
+    assert_eq!([6, 0x1202, 1], test_vm.registers[0..3]);
+    assert_eq!(8, test_vm.pc);
+
+    // code added after loading runs too
+    test_vm.program.extend_from_slice(&[OpCode::INC as u8, 3]);
+    test_vm.pc = 10;
+    assert_eq!(Ok(ExitReason::EndOfProgram), test_vm.run());
+    assert_eq!(1, test_vm.registers[3]);
+  }
+
+  #[test]
+  fn test_run_with_budget_stops_endless_loop() {
+    let mut test_vm = get_vm();
+    test_vm.program = vec![OpCode::LOAD as u8, 0, 0, 0, OpCode::JMP as u8, 0];
+    assert_eq!(Ok(ExitReason::OutOfFuel), test_vm.run_with_budget(11));
+    assert_eq!(0, test_vm.fuel());
+    // the 12th instruction, the JMP, is next
+    assert_eq!(4, test_vm.pc);
+    assert_eq!(None, ExitReason::OutOfFuel.status());
+  }
+
+  #[test]
+  fn test_run_with_budget_resumes() {
+    let costs = CostTable::new().with(OpCode::INC, 2);
+    let mut test_vm = VM::new().with_fuel_costs(costs);
+    test_vm.program = vec![OpCode::INC as u8, 1, OpCode::INC as u8, 1, OpCode::HLT as u8];
+    assert_eq!(Ok(ExitReason::OutOfFuel), test_vm.run_with_budget(3));
+    assert_eq!(1, test_vm.registers[1]);
+    assert_eq!(2, test_vm.pc);
+    assert_eq!(1, test_vm.fuel());
+
+    assert_eq!(Ok(ExitReason::Halted { status: 0 }), test_vm.run_with_budget(10));
+    assert_eq!(2, test_vm.registers[1]);
+    assert_eq!(7, test_vm.fuel());
+    // running off the end costs nothing
+    assert_eq!(Ok(ExitReason::EndOfProgram), test_vm.run_with_budget(0));
+  }
+
+  #[test]
+  fn test_load_program() {
+    let mut test_vm = get_vm();
+    let mut bytecode = Bytecode::new(vec![OpCode::INC as u8, 0, OpCode::INC as u8, 1], vec![7]);
+    bytecode.entry_point = 2;
+    test_vm.load_program(&bytecode.to_bytes()).unwrap();
+    assert_eq!(vec![7], test_vm.ro_data);
+    assert_eq!(Ok(ExitReason::EndOfProgram), test_vm.run());
+    assert_eq!([0, 1], test_vm.registers[0..2]);
+  }
+
+  #[test]
+  fn test_load_program_rejects_raw_bytecode() {
+    let mut test_vm = get_vm();
+    assert_eq!(
+      Err(LoadError::BadMagic),
+      test_vm.load_program(&[OpCode::HLT as u8, 0, 0, 0])
+    );
+  }
+
+  #[test]
+  fn test_store_and_load_widths() {
+    let mut test_vm = get_vm();
+    test_vm.heap.allocate(8).unwrap();
+    test_vm.registers[0] = 2;
+    test_vm.registers[1] = -2;
+    test_vm.program = vec![
+      OpCode::SW as u8, 1, 0, 0, 0,
+      OpCode::LB as u8, 2, 0, 0, 3,
+      OpCode::LBU as u8, 3, 0, 0, 3,
+      OpCode::LH as u8, 4, 0, 0, 2,
+      OpCode::LHU as u8, 5, 0, 0, 2,
+      OpCode::LW as u8, 6, 0, 0, 0,
+      OpCode::SB as u8, 0, 0, 0xFF, 0xFF,
+      OpCode::SH as u8, 0, 0, 0, 4,
+    ];
+    assert_eq!(Ok(ExitReason::EndOfProgram), test_vm.run());
+    assert_eq!(&[0, 2, 0xFF, 0xFF, 0xFF, 0xFE, 0, 2], test_vm.heap.bytes());
+    assert_eq!([-2, 0xFE, -2, 0xFFFE, -2], test_vm.registers[2..7]);
+  }
+
+  #[test]
+  fn test_load_from_data_section() {
+    let mut test_vm = get_vm();
+    test_vm.ro_data = vec![1, 2, 3, 4];
+    test_vm.heap.allocate(1).unwrap();
+    test_vm.heap.bytes_mut()[0] = 5;
+    test_vm.program = vec![OpCode::LW as u8, 0, 1, 0, 0, OpCode::LBU as u8, 1, 1, 0, 4];
+    assert_eq!(Ok(ExitReason::EndOfProgram), test_vm.run());
+    assert_eq!([0x0102_0304, 5], test_vm.registers[0..2]);
+  }
+
+  #[test]
+  fn test_memory_faults() {
+    let cases = [
+      (vec![OpCode::LW as u8, 0, 1, 0, 1], VmErrorKind::HeapFault { address: 1, size: 4 }),
+      (vec![OpCode::LB as u8, 0, 1, 0xFF, 0xFF], VmErrorKind::HeapFault { address: -1, size: 1 }),
+      (vec![OpCode::SB as u8, 0, 1, 0, 1], VmErrorKind::ReadOnlyFault { address: 1 }),
+      (vec![OpCode::SH as u8, 0, 1, 0, 4], VmErrorKind::HeapFault { address: 4, size: 2 }),
+    ];
+    for (program, kind) in cases.iter() {
+      let mut test_vm = get_vm();
+      test_vm.ro_data = vec![0; 2];
+      test_vm.heap.allocate(3).unwrap();
+      test_vm.program = program.clone();
+      assert_eq!(Err(VmError::new(0, program[0], kind.clone())), test_vm.run_once());
+    }
+  }
+
+  #[test]
+  fn test_push_pop_opcodes() {
+    let mut test_vm = get_vm();
+    test_vm.registers[0] = 7;
+    test_vm.registers[1] = -3;
+    test_vm.program = vec![
+      OpCode::PUSH as u8, 0,
+      OpCode::PUSH as u8, 1,
+      OpCode::POP as u8, 2,
+      OpCode::POP as u8, 3,
+    ];
+    assert_eq!(Ok(ExitReason::EndOfProgram), test_vm.run());
+    assert_eq!([-3, 7], test_vm.registers[2..4]);
+    assert_eq!(0, test_vm.stack.pointer());
+  }
+
+  #[test]
+  fn test_call_ret_opcodes() {
+    let mut test_vm = get_vm();
+    test_vm.program = vec![
+      OpCode::CALL as u8, 0, 5,
+      OpCode::INC as u8, 1,
+      // subroutine
+      OpCode::INC as u8, 0,
+      OpCode::RET as u8,
+    ];
+    test_vm.run_once().unwrap();
+    assert_eq!(5, test_vm.pc);
+    assert_eq!(&[3], test_vm.stack.slots());
+    test_vm.run_once().unwrap();
+    test_vm.run_once().unwrap();
+    assert_eq!(3, test_vm.pc);
+    test_vm.run_once().unwrap();
+    assert_eq!([1, 1], test_vm.registers[0..2]);
+    assert_eq!(0, test_vm.stack.pointer());
+  }
+
+  #[test]
+  fn test_stack_overflow_and_underflow() {
+    let mut test_vm = VM::new().with_stack_depth(2);
+    test_vm.program = vec![OpCode::CALL as u8, 0, 0];
+    let error = test_vm.run().unwrap_err();
+    assert_eq!(VmErrorKind::StackOverflow { depth: 2 }, error.kind);
+    assert_eq!(0, error.pc);
+
+    let mut test_vm = get_vm();
+    test_vm.program = vec![OpCode::POP as u8, 0];
+    assert_eq!(VmErrorKind::StackUnderflow, test_vm.run().unwrap_err().kind);
+    test_vm.pc = 0;
+    test_vm.program = vec![OpCode::RET as u8];
+    assert_eq!(VmErrorKind::StackUnderflow, test_vm.run().unwrap_err().kind);
+  }
+
+  #[test]
+  fn test_backtrace() {
+    use bytecode::SymbolSection;
+    let mut test_vm = get_vm();
+    test_vm.symbols = vec![
+      DebugSymbol::new("main".to_string(), SymbolSection::Code, 0),
+      DebugSymbol::new("outer".to_string(), SymbolSection::Code, 4),
+      DebugSymbol::new("inner".to_string(), SymbolSection::Code, 8),
+    ];
+    test_vm.program = vec![
+      OpCode::CALL as u8, 0, 4,
+      OpCode::HLT as u8,
+      // outer
+      OpCode::CALL as u8, 0, 8,
+      OpCode::HLT as u8,
+      // inner
+      OpCode::DIV as u8, 0, 0, 0,
+    ];
+    let error = test_vm.run().unwrap_err();
+    assert_eq!(VmErrorKind::DivisionByZero, error.kind);
+    assert_eq!(
+      vec![Location::new(7, &test_vm.symbols), Location::new(3, &test_vm.symbols)],
+      error.backtrace.frames
+    );
+    assert_eq!("backtrace:\n  0: returns to 7 (outer+3)\n  1: returns to 3 (main+3)", error.backtrace.to_string());
+  }
+
+  #[test]
+  fn test_syscall_opcode() {
+    let functions = HostFunctions::new()
+      .with(1, "double", |vm| {
+        vm.registers[0] *= 2;
+        Ok(())
+      })
+      .with(2, "store", |vm| {
+        let address = vm.allocate(2)?;
+        vm.write_bytes(address, b"ok")?;
+        vm.registers[1] = address as i32;
+        Ok(())
+      });
+    let mut test_vm = VM::new().with_host_functions(functions);
+    test_vm.registers[0] = 21;
+    test_vm.program = vec![OpCode::SYSCALL as u8, 0, 1, OpCode::SYSCALL as u8, 0, 2];
+    assert_eq!(Ok(ExitReason::EndOfProgram), test_vm.run());
+    assert_eq!(42, test_vm.registers[0]);
+    assert_eq!(Ok(&b"ok"[..]), test_vm.read_bytes(i64::from(test_vm.registers[1]), 2));
+    assert_eq!(Some(1), test_vm.host_functions_mut().number_of("double"));
+  }
+
+  #[test]
+  fn test_syscall_registers_function() {
+    let mut test_vm = get_vm();
+    test_vm.host_functions_mut().register(1, "install", |vm| {
+      vm.host_functions_mut().register(2, "answer", |vm| {
+        vm.registers[0] = 42;
+        Ok(())
+      });
+      Ok(())
+    });
+    test_vm.program = vec![OpCode::SYSCALL as u8, 0, 1, OpCode::SYSCALL as u8, 0, 2];
+    assert_eq!(Ok(ExitReason::EndOfProgram), test_vm.run());
+    assert_eq!(42, test_vm.registers[0]);
+    assert_eq!(Some(1), test_vm.host_functions_mut().number_of("install"));
+  }
+
+  #[test]
+  fn test_read_write_bytes_out_of_range() {
+    let mut test_vm = get_vm();
+    test_vm.ro_data = vec![1, 2];
+    test_vm.allocate(4).unwrap();
+    assert_eq!(Err(VmErrorKind::HeapFault { address: 5, size: i64::MAX }), test_vm.read_bytes(5, usize::MAX));
+    assert_eq!(Err(VmErrorKind::HeapFault { address: i64::MAX, size: 1 }), test_vm.read_bytes(i64::MAX, 1));
+    assert_eq!(Err(VmErrorKind::HeapFault { address: i64::MAX, size: 1 }), test_vm.write_bytes(i64::MAX, &[0]));
+    assert_eq!(Err(VmErrorKind::HeapFault { address: i64::MIN, size: 1 }), test_vm.write_bytes(i64::MIN, &[0]));
+    assert_eq!(Ok(&[0, 0][..]), test_vm.read_bytes(4, 2));
+  }
+
+  #[test]
+  fn test_syscall_errors() {
+    let mut test_vm = get_vm();
+    test_vm.host_functions_mut().register(7, "fail", |_| {
+      Err(VmErrorKind::HostFunctionFailed { message: "bad input".to_string() })
+    });
+    test_vm.program = vec![OpCode::SYSCALL as u8, 0, 7, OpCode::SYSCALL as u8, 0, 8];
+    let error = test_vm.run_once().unwrap_err();
+    assert_eq!(VmErrorKind::HostFunctionFailed { message: "bad input".to_string() }, error.kind);
+    assert_eq!("host function failed: bad input at pc 0 (opcode 0x39)", error.to_string());
+    test_vm.pc = 3;
+    let error = test_vm.run_once().unwrap_err();
+    assert_eq!(VmErrorKind::UnknownHostFunction { number: 8 }, error.kind);
+  }
+
+  #[test]
+  #[cfg(feature = "std")]
+  fn test_print_opcodes() {
+    let output = SharedBuffer::new();
+    let mut test_vm = VM::new().with_output(output.clone());
+    test_vm.ro_data = b"hi\0".to_vec();
+    test_vm.registers[0] = -42;
+    test_vm.registers[1] = i32::from(b'!');
+    test_vm.program = vec![
+      OpCode::PRTI as u8, 0,
+      OpCode::PRTC as u8, 1,
+      OpCode::PRTS as u8, 2,
+    ];
+    assert_eq!(Ok(ExitReason::EndOfProgram), test_vm.run());
+    assert_eq!("-42!hi", output.to_string_lossy());
+  }
+
+  #[test]
+  #[cfg(feature = "std")]
+  fn test_prts_without_terminator() {
+    let mut test_vm = VM::new().with_output(SharedBuffer::new());
+    test_vm.ro_data = b"hi".to_vec();
+    test_vm.program = vec![OpCode::PRTS as u8, 0];
+    assert_eq!(VmErrorKind::HeapFault { address: 2, size: 1 }, test_vm.run().unwrap_err().kind);
+  }
+
+  #[test]
+  #[cfg(feature = "std")]
+  fn test_read_opcodes() {
+    let mut test_vm = VM::new().with_input(&b" 17 \nhello\r\nnope\n"[..]);
+    test_vm.program = vec![
+      OpCode::READI as u8, 0,
+      OpCode::READS as u8, 1,
+      OpCode::READI as u8, 2,
+    ];
+    test_vm.run_once().unwrap();
+    test_vm.run_once().unwrap();
+    assert_eq!(17, test_vm.registers[0]);
+    assert_eq!(Ok(b"hello".to_vec()), test_vm.read_string(i64::from(test_vm.registers[1])));
+    let error = test_vm.run_once().unwrap_err();
+    assert_eq!(VmErrorKind::BadInput { input: "nope".to_string() }, error.kind);
+    test_vm.pc = 0;
+    assert_eq!(VmErrorKind::EndOfInput, test_vm.run_once().unwrap_err().kind);
+  }
+
+  #[test]
+  #[cfg(not(feature = "std"))]
+  fn test_console_opcodes_without_std() {
+    let mut test_vm = get_vm();
+    test_vm.program = vec![OpCode::PRTI as u8, 0, OpCode::READI as u8, 0];
+    let message = "no console without the std feature".to_string();
+    assert_eq!(VmErrorKind::Io { message: message.clone() }, test_vm.run_once().unwrap_err().kind);
+    test_vm.pc = 2;
+    assert_eq!(VmErrorKind::Io { message }, test_vm.run_once().unwrap_err().kind);
+  }
+
+  #[test]
+  fn test_exit_opcode() {
+    let mut test_vm = get_vm();
+    test_vm.registers[3] = 7;
+    test_vm.program = vec![OpCode::EXIT as u8, 3, OpCode::INC as u8, 0];
+    assert_eq!(Ok(ExitReason::Halted { status: 7 }), test_vm.run());
+    assert_eq!(2, test_vm.pc());
+    assert_eq!(0, test_vm.registers[0]);
+    assert_eq!(Some(7), ExitReason::Halted { status: 7 }.status());
+    assert_eq!(Some(0), ExitReason::EndOfProgram.status());
+  }
+}
@@ -0,0 +1,102 @@
+use prelude::*;
+use vm::error::VmErrorKind;
+
+/// Stack depth of a VM that was not given one, in slots
+pub const DEFAULT_STACK_DEPTH: usize = 1024;
+
+/// The call stack, each slot holds a register value pushed by PUSH or a return address pushed by CALL
+#[derive(Debug)]
+pub struct Stack {
+  slots: Vec<i32>,
+  /// Indices of the slots CALL pushed return addresses into, for backtraces
+  frames: Vec<usize>,
+  max_depth: usize,
+}
+
+impl Default for Stack {
+  fn default() -> Stack {
+    Stack::new(DEFAULT_STACK_DEPTH)
+  }
+}
+
+impl Stack {
+  pub fn new(max_depth: usize) -> Stack {
+    Stack {
+      slots: vec![],
+      frames: vec![],
+      max_depth
+    }
+  }
+
+  /// Number of slots in use, which is where the next push goes
+  pub fn pointer(&self) -> usize {
+    self.slots.len()
+  }
+
+  pub fn max_depth(&self) -> usize {
+    self.max_depth
+  }
+
+  /// The slots in use, bottom first
+  pub fn slots(&self) -> &[i32] {
+    &self.slots
+  }
+
+  pub fn push(&mut self, value: i32) -> Result<(), VmErrorKind> {
+    if self.slots.len() >= self.max_depth {
+      return Err(VmErrorKind::StackOverflow { depth: self.max_depth });
+    }
+    self.slots.push(value);
+    Ok(())
+  }
+
+  /// Pushes the return address of a CALL
+  pub fn push_frame(&mut self, return_address: i32) -> Result<(), VmErrorKind> {
+    self.push(return_address)?;
+    self.frames.push(self.slots.len() - 1);
+    Ok(())
+  }
+
+  pub fn pop(&mut self) -> Result<i32, VmErrorKind> {
+    let value = self.slots.pop().ok_or(VmErrorKind::StackUnderflow)?;
+    // POP and RET do not have to pair with PUSH and CALL, a frame ends whenever its slot goes
+    if self.frames.last() == Some(&self.slots.len()) {
+      self.frames.pop();
+    }
+    Ok(value)
+  }
+
+  /// Return addresses of the active calls, innermost first
+  pub fn return_addresses(&self) -> Vec<i32> {
+    self.frames.iter().rev().map(|&slot| self.slots[slot]).collect()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_push_pop() {
+    let mut stack = Stack::new(2);
+    stack.push(1).unwrap();
+    stack.push(2).unwrap();
+    assert_eq!(2, stack.pointer());
+    assert_eq!(Err(VmErrorKind::StackOverflow { depth: 2 }), stack.push(3));
+    assert_eq!(Ok(2), stack.pop());
+    assert_eq!(Ok(1), stack.pop());
+    assert_eq!(Err(VmErrorKind::StackUnderflow), stack.pop());
+  }
+
+  #[test]
+  fn test_frames() {
+    let mut stack = Stack::default();
+    stack.push_frame(10).unwrap();
+    stack.push(1).unwrap();
+    stack.push_frame(20).unwrap();
+    assert_eq!(vec![20, 10], stack.return_addresses());
+    stack.pop().unwrap();
+    stack.pop().unwrap();
+    assert_eq!(vec![10], stack.return_addresses());
+  }
+}